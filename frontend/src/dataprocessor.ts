@@ -1,13 +1,26 @@
-import type { ActivePlayersMap, Choice, ChoiceMap, GameState, Player } from '$base/types';
+import type {
+	ActivePlayersMap,
+	Choice,
+	ChoiceMap,
+	ChoiceOption,
+	GameState,
+	Player,
+} from '$base/types';
 
 export type PlayerChoice = { player: Player; lie: boolean; id: number };
-export type PlayerChoices = {
-	a: Array<PlayerChoice>;
-	b: Array<PlayerChoice>;
-};
+// One list per option, in the round's order
+export type PlayerChoices = Array<Array<PlayerChoice>>;
+
+export function optionLetter(position: number): ChoiceOption {
+	return String.fromCharCode('a'.charCodeAt(0) + position);
+}
+
+export function optionPosition(option: ChoiceOption): number {
+	return option.charCodeAt(0) - 'a'.charCodeAt(0);
+}
 
 export function getChoices(gameState: GameState | null): PlayerChoices {
-	const struct: PlayerChoices = { a: [], b: [] };
+	const struct: PlayerChoices = (gameState?.round?.options ?? []).map(() => []);
 	if (!gameState) {
 		return struct;
 	}
@@ -19,7 +32,7 @@ export function getChoices(gameState: GameState | null): PlayerChoices {
 			continue;
 		}
 
-		struct[choice.option].push({ player, lie: choice.lie, id: choice.id });
+		struct[optionPosition(choice.option)]?.push({ player, lie: choice.lie, id: choice.id });
 	}
 
 	const sorter = (a: PlayerChoice, b: PlayerChoice) => {
@@ -32,8 +45,9 @@ export function getChoices(gameState: GameState | null): PlayerChoices {
 		return 0;
 	};
 
-	struct.a.sort(sorter);
-	struct.b.sort(sorter);
+	for (const optionChoices of struct) {
+		optionChoices.sort(sorter);
+	}
 
 	return struct;
 }
@@ -0,0 +1,23 @@
+import { v4 as generateUuid } from 'uuid';
+import { getWebsocketConnection, pushResponseStack } from '$base/game';
+import { display as displayStore } from '$base/stores';
+import type { Room } from '$base/types';
+
+export function setDisplay(room: Room) {
+	displayStore.set(room);
+}
+
+export function login(): Promise<void> {
+	return new Promise((resolve) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'login-display',
+			}),
+		);
+
+		pushResponseStack(responseId, resolve);
+	});
+}
@@ -2,37 +2,66 @@ import { PUBLIC_GAMESERVER_URL } from '$env/static/public';
 import { toast } from '@zerodevx/svelte-toast';
 import { get } from 'svelte/store';
 import { v4 as generateUuid } from 'uuid';
-import { browserEnv, websocketConnection } from '$base/stores';
+import { browserEnv, server, websocketConnection } from '$base/stores';
 import { gameState as gameStateStore } from '$base/stores';
-import { setPlayer, setPlayerIfSelf } from '$base/player';
-import { setOrganizer } from '$base/organizer';
-
-import type { Choice, GameState, Player, Round, WebSocketMessage } from '$base/types';
+import { setPlayerIdentity, setPlayerIfSelf } from '$base/player';
+import {
+	setGamePlan,
+	setGames,
+	setOrganizer,
+	setRooms,
+	setVoteHistory,
+} from '$base/organizer';
+import { setDisplay } from '$base/display';
+
+import type {
+	Choice,
+	ClientKind,
+	GameState,
+	Player,
+	Room,
+	Round,
+	RoundResult,
+	RoundTimer,
+	ServerHello,
+	WebSocketMessage,
+} from '$base/types';
 import { convertChoicesObjectToMap, convertPlayersObjectToMap } from './dataprocessor';
 
-const awaitResponseStack: Map<string, () => void> = new Map();
+// Bump together with PROTOCOL_VERSION in src/postmaster/protocol.rs
+const PROTOCOL_VERSION = 1;
+
+type ResponseCallbacks = { callback: () => void; failureCallback?: () => void };
 
-export function pushResponseStack(id: string, callback: () => void, timeoutCallback?: () => void) {
-	awaitResponseStack.set(id, callback);
+const awaitResponseStack: Map<string, ResponseCallbacks> = new Map();
 
-	if (timeoutCallback) {
+export function pushResponseStack(id: string, callback: () => void, failureCallback?: () => void) {
+	awaitResponseStack.set(id, { callback, failureCallback });
+
+	if (failureCallback) {
 		setTimeout(() => {
-			if (!awaitResponseStack.get(id)) {
+			if (!awaitResponseStack.delete(id)) {
 				return;
 			}
 
-			timeoutCallback();
+			failureCallback();
 		}, 5000);
 	}
 }
 
-function resolveResponseStack(id: string) {
-	const resolver = awaitResponseStack.get(id);
-	if (resolver === undefined) {
+function resolveResponseStack(id: string, failed: boolean) {
+	const callbacks = awaitResponseStack.get(id);
+	if (callbacks === undefined) {
 		return;
 	}
+	awaitResponseStack.delete(id);
 
-	resolver();
+	// Callers without a failure handler are still released, the ng toast tells what went wrong
+	if (failed && callbacks.failureCallback) {
+		callbacks.failureCallback();
+	} else {
+		callbacks.callback();
+	}
 }
 
 export async function connect() {
@@ -80,6 +109,7 @@ function connectWebsocket(): Promise<WebSocket> {
 
 		socket.onopen = () => {
 			console.info('WebSocket connection established.');
+			sayHello(socket);
 			resolve(socket);
 		};
 
@@ -87,9 +117,44 @@ function connectWebsocket(): Promise<WebSocket> {
 			const parsed: WebSocketMessage = JSON.parse(event.data);
 			handleMessage(parsed);
 		};
+
+		socket.onclose = () => {
+			console.info('WebSocket connection closed.');
+			websocketConnection.update((wsc) => {
+				wsc.connection = null;
+				wsc.state = 'disconnected';
+				return wsc;
+			});
+		};
 	});
 }
 
+// Which part of the frontend this is, told to the server in the hello
+function getClientKind(): ClientKind {
+	if (location.pathname.startsWith('/organizer-panel')) {
+		return 'organizer';
+	}
+	if (location.pathname.startsWith('/display')) {
+		return 'display';
+	}
+
+	return 'player';
+}
+
+function sayHello(socket: WebSocket) {
+	socket.send(
+		JSON.stringify({
+			responseId: generateUuid(),
+			action: 'hello',
+			payload: { version: PROTOCOL_VERSION, kind: getClientKind() },
+		}),
+	);
+}
+
+function setServer(hello: ServerHello) {
+	server.set(hello);
+}
+
 export function getWebsocketConnection(): WebSocket {
 	const socket = get(websocketConnection);
 	if (socket.state !== 'connected' || !socket.connection) {
@@ -105,13 +170,20 @@ function handleMessage(message: WebSocketMessage) {
 	if (message.action == 'ok') {
 		// None
 	} else if (message.action == 'ng') {
+		console.warn(`Request failed (${message.code}): ${message.payload}`);
 		toast.push(message.payload, { classes: ['toast failure'] });
+	} else if (message.action == 'hello') {
+		setServer(message.payload);
 	} else if (message.action == 'show-message') {
 		toast.push(message.payload, { classes: ['toast'] });
+	} else if (message.action == 'server-shutting-down') {
+		toast.push(message.payload, { classes: ['toast failure'], initial: 0 });
 	} else if (message.action == 'set-player') {
-		setPlayer(message.payload);
+		setPlayerIdentity(message.payload);
 	} else if (message.action == 'set-organizer') {
 		setOrganizer(message.payload);
+	} else if (message.action == 'set-display') {
+		setDisplay(message.payload);
 	} else if (message.action == 'refresh-active-players-list') {
 		setActivePlayers(message.payload);
 	} else if (message.action == 'update-player') {
@@ -124,10 +196,24 @@ function handleMessage(message: WebSocketMessage) {
 		setPlayerChoice(message.payload);
 	} else if (message.action == 'set-choices') {
 		setChoicesMap(message.payload);
+	} else if (message.action == 'set-round-result') {
+		setRoundResult(message.payload);
+	} else if (message.action == 'set-timer') {
+		setTimer(message.payload);
+	} else if (message.action == 'set-game-plan') {
+		setGamePlan(message.payload);
+	} else if (message.action == 'set-room') {
+		setRoom(message.payload);
+	} else if (message.action == 'set-rooms') {
+		setRooms(message.payload);
+	} else if (message.action == 'set-games') {
+		setGames(message.payload);
+	} else if (message.action == 'set-vote-history') {
+		setVoteHistory(message.payload);
 	}
 
 	if (message.responseId) {
-		resolveResponseStack(message.responseId);
+		resolveResponseStack(message.responseId, message.action == 'ng');
 	}
 }
 
@@ -146,11 +232,31 @@ export function getGameState(): Promise<void> {
 	});
 }
 
+export function joinRoom(code: string): Promise<void> {
+	return new Promise((resolve) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'join-room',
+				payload: code,
+			}),
+		);
+
+		pushResponseStack(responseId, resolve);
+	});
+}
+
 function getBlankGameState(): GameState {
 	return {
+		room: null,
+		game: null,
 		round: null,
 		players: new Map(),
 		choices: new Map(),
+		result: null,
+		timer: null,
 	};
 }
 
@@ -180,6 +286,17 @@ export function updatePlayer(player: Player) {
 	setPlayerIfSelf(player);
 }
 
+function setRoom(room: Room) {
+	gameStateStore.update((gameState) => {
+		if (gameState === null) {
+			gameState = getBlankGameState();
+		}
+
+		gameState.room = room;
+		return gameState;
+	});
+}
+
 function setRound(round: Round) {
 	gameStateStore.update((gameState) => {
 		if (gameState === null) {
@@ -187,6 +304,9 @@ function setRound(round: Round) {
 		}
 
 		gameState.round = round;
+		if (round.state != 'show-results') {
+			gameState.result = null;
+		}
 		return gameState;
 	});
 }
@@ -223,3 +343,25 @@ export function setChoicesMap(choicesMap: object) {
 		return gameState;
 	});
 }
+
+function setRoundResult(result: RoundResult) {
+	gameStateStore.update((gameState) => {
+		if (gameState === null) {
+			gameState = getBlankGameState();
+		}
+
+		gameState.result = result;
+		return gameState;
+	});
+}
+
+function setTimer(timer: RoundTimer | null) {
+	gameStateStore.update((gameState) => {
+		if (gameState === null) {
+			gameState = getBlankGameState();
+		}
+
+		gameState.timer = timer;
+		return gameState;
+	});
+}
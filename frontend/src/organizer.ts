@@ -1,14 +1,47 @@
 import { v4 as generateUuid } from 'uuid';
 import { getWebsocketConnection, pushResponseStack } from '$base/game';
-import { organizer as organizerStore } from '$base/stores';
+import {
+	gamePlan as gamePlanStore,
+	games as gamesStore,
+	organizer as organizerStore,
+	rooms as roomsStore,
+	voteHistory as voteHistoryStore,
+} from '$base/stores';
 
-import type { Choice, Organizer, Player, Round } from './types';
+import type {
+	Choice,
+	ChoiceOption,
+	Game,
+	Organizer,
+	PlannedRound,
+	Player,
+	Room,
+	RoundState,
+	SetRound,
+	VoteHistory,
+} from './types';
 
 export function setOrganizer(organizer: Organizer) {
 	organizerStore.set(organizer);
 }
 
-export function login(password: string): Promise<void> {
+export function setGamePlan(plan: Array<PlannedRound>) {
+	gamePlanStore.set(plan);
+}
+
+export function setRooms(rooms: Array<Room>) {
+	roomsStore.set(rooms);
+}
+
+export function setGames(games: Array<Game>) {
+	gamesStore.set(games);
+}
+
+export function setVoteHistory(history: VoteHistory) {
+	voteHistoryStore.set(history);
+}
+
+export function login(username: string, password: string): Promise<void> {
 	return new Promise((resolve) => {
 		const socket = getWebsocketConnection();
 		const responseId = generateUuid();
@@ -16,7 +49,10 @@ export function login(password: string): Promise<void> {
 			JSON.stringify({
 				responseId,
 				action: 'login-organizer',
-				payload: password,
+				payload: {
+					username,
+					password,
+				},
 			}),
 		);
 
@@ -24,7 +60,10 @@ export function login(password: string): Promise<void> {
 	});
 }
 
-export function updateRound(round: Round): Promise<void> {
+export function updateRound(
+	round: Omit<SetRound, 'override'>,
+	overrideTransition = false,
+): Promise<void> {
 	return new Promise((resolve, reject) => {
 		const socket = getWebsocketConnection();
 		const responseId = generateUuid();
@@ -32,7 +71,22 @@ export function updateRound(round: Round): Promise<void> {
 			JSON.stringify({
 				responseId,
 				action: 'set-round',
-				payload: round,
+				payload: { ...round, override: overrideTransition },
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function advanceRoundState(): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'advance-round-state',
 			}),
 		);
 
@@ -101,3 +155,201 @@ export function changePlayerPoint(player: Player, amount: number): Promise<void>
 		pushResponseStack(responseId, resolve, reject);
 	});
 }
+
+export function setLateVote(player: Player, option: ChoiceOption): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'set-late-vote',
+				payload: {
+					playerId: player.id,
+					option,
+				},
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function startTimer(seconds: number, advanceTo: RoundState | null = null): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'start-timer',
+				payload: {
+					seconds,
+					advanceTo,
+				},
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function extendTimer(seconds: number): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'extend-timer',
+				payload: seconds,
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function controlTimer(
+	action: 'pause-timer' | 'resume-timer' | 'cancel-timer',
+): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action,
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function loadPlan(format: 'toml' | 'json', content: string): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'load-plan',
+				payload: {
+					format,
+					content,
+				},
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function getGamePlan(): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'get-game-plan',
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function nextRound(): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'next-round',
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function createRoom(name: string): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'create-room',
+				payload: name,
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function getRooms(): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'get-rooms',
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function startGame(name: string): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'start-game',
+				payload: name,
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function getGames(): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'get-games',
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
+
+export function getVoteHistory(roundId: number, playerId: number): Promise<void> {
+	return new Promise((resolve, reject) => {
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'get-vote-history',
+				payload: { roundId, playerId },
+			}),
+		);
+
+		pushResponseStack(responseId, resolve, reject);
+	});
+}
@@ -4,10 +4,17 @@ import { player as playerStore } from '$base/stores';
 import { get } from 'svelte/store';
 import type { Player } from '$base/types';
 
+const sessionTokenKey = 'ucv-session-token';
+
 export function setPlayer(player: Player) {
 	playerStore.set(player);
 }
 
+export function setPlayerIdentity({ player, sessionToken }: { player: Player; sessionToken: string }) {
+	localStorage.setItem(sessionTokenKey, sessionToken);
+	setPlayer(player);
+}
+
 export function setPlayerIfSelf(player: Player) {
 	if (!get(playerStore)) {
 		return;
@@ -35,6 +42,28 @@ export function login(name: string): Promise<void> {
 	});
 }
 
+export function resumeSession(): Promise<void> {
+	return new Promise((resolve) => {
+		const sessionToken = localStorage.getItem(sessionTokenKey);
+		if (!sessionToken) {
+			resolve();
+			return;
+		}
+
+		const socket = getWebsocketConnection();
+		const responseId = generateUuid();
+		socket.send(
+			JSON.stringify({
+				responseId,
+				action: 'resume-session',
+				payload: sessionToken,
+			}),
+		);
+
+		pushResponseStack(responseId, resolve);
+	});
+}
+
 export function setChoice(choiceOption: string): Promise<void> {
 	return new Promise((resolve, reject) => {
 		const socket = getWebsocketConnection();
@@ -0,0 +1,69 @@
+// Generated by `ucv-game export-protocol`, do not edit by hand.
+
+export type ErrorCode = "parse_error" | "unknown_action" | "unsupported_version" | "forbidden" | "invalid_state" | "invalid_input" | "not_found" | "conflict" | "internal";
+
+export type ClientKind = "player" | "organizer" | "display" | "bot";
+
+export type ClientHello = { version: number, kind: ClientKind, };
+
+export type ServerHello = { version: number, minVersion: number, server: string, actions: Array<string>, features: Array<string>, };
+
+export type Player = { id: number, name: string, points: number | null, canVote: boolean | null, };
+
+export type Room = { id: number, code: string, name: string, };
+
+export type Game = { id: number, name: string, startedAt: number, archivedAt: number | null, };
+
+export type Organizer = { id: number, username: string, name: string, };
+
+export type RoundState = "standby" | "show-question" | "show-choices" | "voting-time" | "voting-locked" | "show-votes" | "defense" | "show-results";
+
+export type Round = { id: number, number: number, phase: number, state: RoundState, question: string, options: Array<string>, choiceA: string | null, choiceB: string | null, };
+
+export type PlannedRound = { number: number, phase: number, question: string, options: Array<string>, votingSeconds: number | null, };
+
+export type ChoiceOption = string;
+
+export type Choice = { id: number, option: ChoiceOption, lie: boolean, };
+
+export type ChoiceEvent = { option: ChoiceOption, submittedAt: number, };
+
+export type RoundResult = { roundId: number, minority: ChoiceOption | null, votes: Record<string, number>, winners: Array<number>, losers: Array<number>, };
+
+export type TimerStatus = { roundId: number, state: RoundState, advanceTo: RoundState, running: boolean, remainingMs: number, deadline: number | null, };
+
+export type GameState = { room: Room, game: Game, round: Round | null, players: { [key in number]?: Player }, choices: { [key in number]?: Choice }, result: RoundResult | null, timer: TimerStatus | null, };
+
+export type PlanFormat = "toml" | "json";
+
+export type LoginOrganizer = { username: string, password: string, };
+
+export type SetRound = { id: number, number: number, phase: number, state: RoundState, question: string, options?: Array<string>, choiceA?: string, choiceB?: string, override?: boolean, };
+
+export type LoadPlan = { format: PlanFormat, content: string, };
+
+export type StartTimer = { seconds: number, advanceTo?: RoundState, };
+
+export type MarkChoice = { id: number, lie?: boolean, };
+
+export type MarkPlayer = { id: number, canVote?: boolean, };
+
+export type PlayerPoints = { id: number, points?: number, };
+
+export type LateVote = { playerId: number, option: ChoiceOption, };
+
+export type VoteHistoryQuery = { roundId: number, playerId: number, };
+
+export type WebSocketMessageAction = { "action": "hello", "payload": ClientHello } | { "action": "login-player", "payload": string } | { "action": "resume-session", "payload": string } | { "action": "join-room", "payload": string } | { "action": "login-organizer", "payload": LoginOrganizer } | { "action": "login-display" } | { "action": "create-room", "payload": string } | { "action": "get-rooms" } | { "action": "start-game", "payload": string } | { "action": "get-games" } | { "action": "get-game-state" } | { "action": "set-round", "payload": SetRound } | { "action": "advance-round-state" } | { "action": "load-plan", "payload": LoadPlan } | { "action": "get-game-plan" } | { "action": "next-round" } | { "action": "start-timer", "payload": StartTimer } | { "action": "pause-timer" } | { "action": "resume-timer" } | { "action": "extend-timer", "payload": number } | { "action": "cancel-timer" } | { "action": "set-choice", "payload": ChoiceOption } | { "action": "set-vote-is-lie", "payload": MarkChoice } | { "action": "set-late-vote", "payload": LateVote } | { "action": "set-player-can-vote", "payload": MarkPlayer } | { "action": "set-player-points", "payload": PlayerPoints } | { "action": "get-vote-history", "payload": VoteHistoryQuery };
+
+export type WebSocketMessage = { responseId: string | null, } & ({ "action": "hello", "payload": ClientHello } | { "action": "login-player", "payload": string } | { "action": "resume-session", "payload": string } | { "action": "join-room", "payload": string } | { "action": "login-organizer", "payload": LoginOrganizer } | { "action": "login-display" } | { "action": "create-room", "payload": string } | { "action": "get-rooms" } | { "action": "start-game", "payload": string } | { "action": "get-games" } | { "action": "get-game-state" } | { "action": "set-round", "payload": SetRound } | { "action": "advance-round-state" } | { "action": "load-plan", "payload": LoadPlan } | { "action": "get-game-plan" } | { "action": "next-round" } | { "action": "start-timer", "payload": StartTimer } | { "action": "pause-timer" } | { "action": "resume-timer" } | { "action": "extend-timer", "payload": number } | { "action": "cancel-timer" } | { "action": "set-choice", "payload": ChoiceOption } | { "action": "set-vote-is-lie", "payload": MarkChoice } | { "action": "set-late-vote", "payload": LateVote } | { "action": "set-player-can-vote", "payload": MarkPlayer } | { "action": "set-player-points", "payload": PlayerPoints } | { "action": "get-vote-history", "payload": VoteHistoryQuery });
+
+export type PlayerIdentity = { player: Player, sessionToken: string, };
+
+export type PlayerChoice = { player: Player, choice: Choice, };
+
+export type VoteHistory = { roundId: number, playerId: number, events: Array<ChoiceEvent>, };
+
+export type WebSocketResponseAction = { "action": "ok" } | { "action": "ng", "payload": string } | { "action": "hello", "payload": ServerHello } | { "action": "set-player", "payload": PlayerIdentity } | { "action": "set-organizer", "payload": Organizer } | { "action": "set-display", "payload": Room } | { "action": "refresh-active-players-list", "payload": { [key in number]?: Player } } | { "action": "update-player", "payload": Player } | { "action": "set-game-state", "payload": GameState } | { "action": "set-room", "payload": Room } | { "action": "set-rooms", "payload": Array<Room> } | { "action": "set-games", "payload": Array<Game> } | { "action": "set-round", "payload": Round } | { "action": "set-round-result", "payload": RoundResult } | { "action": "set-timer", "payload": TimerStatus | null } | { "action": "set-choices", "payload": { [key in number]?: Choice } } | { "action": "set-player-choice", "payload": PlayerChoice } | { "action": "set-game-plan", "payload": Array<PlannedRound> } | { "action": "set-vote-history", "payload": VoteHistory } | { "action": "server-shutting-down", "payload": string };
+
+export type WebSocketResponse = { responseId: string | null, code: ErrorCode | null, } & ({ "action": "ok" } | { "action": "ng", "payload": string } | { "action": "hello", "payload": ServerHello } | { "action": "set-player", "payload": PlayerIdentity } | { "action": "set-organizer", "payload": Organizer } | { "action": "set-display", "payload": Room } | { "action": "refresh-active-players-list", "payload": { [key in number]?: Player } } | { "action": "update-player", "payload": Player } | { "action": "set-game-state", "payload": GameState } | { "action": "set-room", "payload": Room } | { "action": "set-rooms", "payload": Array<Room> } | { "action": "set-games", "payload": Array<Game> } | { "action": "set-round", "payload": Round } | { "action": "set-round-result", "payload": RoundResult } | { "action": "set-timer", "payload": TimerStatus | null } | { "action": "set-choices", "payload": { [key in number]?: Choice } } | { "action": "set-player-choice", "payload": PlayerChoice } | { "action": "set-game-plan", "payload": Array<PlannedRound> } | { "action": "set-vote-history", "payload": VoteHistory } | { "action": "server-shutting-down", "payload": string });
@@ -1,8 +1,19 @@
 import { writable, type Writable } from 'svelte/store';
-import type { GameState, Organizer, Player, WebSocketConnection } from '$base/types';
+import type {
+	Game,
+	GameState,
+	Organizer,
+	PlannedRound,
+	Player,
+	Room,
+	ServerHello,
+	VoteHistory,
+	WebSocketConnection,
+} from '$base/types';
 
-export const browserEnv: Writable<{ server: string | null }> = writable({
+export const browserEnv: Writable<{ server: string | null; room: string | null }> = writable({
 	server: null,
+	room: null,
 });
 
 export const websocketConnection: Writable<WebSocketConnection> = writable({
@@ -10,8 +21,24 @@ export const websocketConnection: Writable<WebSocketConnection> = writable({
 	connection: null,
 });
 
+// What the server announced in reply to our hello
+export const server: Writable<ServerHello | null> = writable(null);
+
 export const player: Writable<Player | null> = writable(null);
 
 export const organizer: Writable<Organizer | null> = writable(null);
 
+// The room shown when this client is a read-only display
+export const display: Writable<Room | null> = writable(null);
+
 export const gameState: Writable<GameState | null> = writable(null);
+
+export const gamePlan: Writable<Array<PlannedRound>> = writable([]);
+
+export const rooms: Writable<Array<Room>> = writable([]);
+
+// Every game played in the current room, the last one is running
+export const games: Writable<Array<Game>> = writable([]);
+
+// The votes of the player an organizer is looking into
+export const voteHistory: Writable<VoteHistory | null> = writable(null);
@@ -1,60 +1,59 @@
+// Wire types are generated from the server, see protocol.ts
+import type {
+	Choice,
+	ErrorCode,
+	Game,
+	GameState as ProtocolGameState,
+	Player,
+	Room,
+	TimerStatus,
+	WebSocketResponse,
+} from '$base/protocol';
+
+export type {
+	Choice,
+	ChoiceEvent,
+	ChoiceOption,
+	ClientKind,
+	ErrorCode,
+	Game,
+	Organizer,
+	PlannedRound,
+	Player,
+	Room,
+	Round,
+	RoundResult,
+	RoundState,
+	ServerHello,
+	SetRound,
+	VoteHistory,
+} from '$base/protocol';
+
 export type WebSocketConnection = {
 	state: 'disconnected' | 'connected' | 'connecting' | 'error';
 	connection: WebSocket | null;
 };
 
+// Any message from the server, its payload is checked by the handler of its action
 export type WebSocketMessage = {
 	responseId: string | null;
-	action: string;
+	action: WebSocketResponse['action'];
 	payload: any;
+	// Only set on "ng" responses
+	code: ErrorCode | null;
 };
 
 export type PlayerID = number;
 
-export type Player = {
-	id: PlayerID;
-	name: string;
-	points: number;
-	canVote?: boolean;
-};
-
-export type Organizer = {
-	name: string;
-};
-
-export type RoundState =
-	| 'standby'
-	| 'show-question'
-	| 'show-choices'
-	| 'voting-time'
-	| 'voting-locked'
-	| 'show-votes'
-	| 'defense'
-	| 'show-results';
-
-export type Round = {
-	id: number;
-	number: number;
-	phase: number;
-	state: RoundState;
-	question: string;
-	choiceA: string;
-	choiceB: string;
-};
-
-export type ChoiceOption = 'a' | 'b';
-
-export type Choice = {
-	id: number;
-	option: ChoiceOption;
-	lie: boolean;
-};
-
 export type ChoiceMap = Map<PlayerID, Choice>;
 export type ActivePlayersMap = Map<PlayerID, Player>;
 
-export type GameState = {
-	round: Round | null;
+export type RoundTimer = TimerStatus;
+
+// The game state with its players and choices turned into maps
+export type GameState = Omit<ProtocolGameState, 'room' | 'game' | 'players' | 'choices'> & {
+	room: Room | null;
+	game: Game | null;
 	players: ActivePlayersMap;
 	choices: ChoiceMap;
 };
@@ -0,0 +1,108 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+
+use super::config::{DatabaseBackend, SlowClientPolicy};
+
+// Every flag can also be given through its environment variable; flags win over variables
+#[derive(Parser, Debug)]
+#[command(version, about = "UCV game server")]
+pub struct Arguments {
+	#[command(subcommand)]
+	pub command: Option<Command>,
+
+	/// Path to the TOML configuration file
+	#[arg(short, long, env = "UCV_CONFIG")]
+	pub config: Option<PathBuf>,
+
+	/// Address the websocket server listens on
+	#[arg(long, env = "UCV_LISTEN_ADDRESS")]
+	pub listen_address: Option<SocketAddr>,
+
+	/// Where the game is stored, memory keeps nothing after the server stops
+	#[arg(long, env = "UCV_DATABASE_BACKEND", value_enum)]
+	pub database_backend: Option<DatabaseBackend>,
+
+	/// Path to the SQLite database file
+	#[arg(long, env = "UCV_DATABASE_PATH")]
+	pub database_path: Option<String>,
+
+	/// Number of tokio worker threads
+	#[arg(long, env = "UCV_WORKER_THREADS")]
+	pub worker_threads: Option<usize>,
+
+	/// Log filter, in env_logger syntax, taking precedence over RUST_LOG
+	#[arg(long, env = "UCV_LOG_FILTER")]
+	pub log_filter: Option<String>,
+
+	/// Longest player name accepted, in characters
+	#[arg(long, env = "UCV_MAX_PLAYER_NAME_LENGTH")]
+	pub max_player_name_length: Option<usize>,
+
+	/// Largest websocket message accepted from a client, in bytes
+	#[arg(long, env = "UCV_MAX_MESSAGE_SIZE")]
+	pub max_message_size: Option<usize>,
+
+	/// Number of messages queued for the gamemaster before clients have to wait
+	#[arg(long, env = "UCV_GAMEMASTER_INBOX_SIZE")]
+	pub gamemaster_inbox_size: Option<usize>,
+
+	/// Number of messages queued for each client before they are dropped
+	#[arg(long, env = "UCV_CLIENT_OUTBOX_SIZE")]
+	pub client_outbox_size: Option<usize>,
+
+	/// What to do with clients whose outbox is full
+	#[arg(long, env = "UCV_SLOW_CLIENT_POLICY", value_enum)]
+	pub slow_client_policy: Option<SlowClientPolicy>,
+
+	/// Consecutive dropped messages before a slow client is disconnected
+	#[arg(long, env = "UCV_SLOW_CLIENT_LAG_THRESHOLD")]
+	pub slow_client_lag_threshold: Option<usize>,
+
+	/// Points awarded to each player in the minority when a round is resolved
+	#[arg(long, env = "UCV_POINTS_PER_WIN")]
+	pub points_per_win: Option<usize>,
+
+	/// Seconds to wait for clients to disconnect when shutting down
+	#[arg(long, env = "UCV_SHUTDOWN_GRACE_PERIOD")]
+	pub shutdown_grace_period: Option<u64>,
+
+	/// Seconds a player can resume their session with its token
+	#[arg(long, env = "UCV_SESSION_LIFETIME")]
+	pub session_lifetime: Option<u64>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+	/// Create an organizer account and exit
+	AddOrganizer {
+		/// Username used to log in to the panel
+		username: String,
+
+		/// Name shown in the panel and the logs, defaults to the username
+		#[arg(long)]
+		name: Option<String>,
+
+		/// Password for the new account
+		#[arg(long, env = "UCV_NEW_ORGANIZER_PASSWORD", hide_env_values = true)]
+		password: String,
+	},
+
+	/// List the schema migrations the database still needs and exit, without applying them
+	PendingMigrations,
+
+	/// Write the JSON Schema and TypeScript definitions of the protocol and exit
+	ExportProtocol {
+		/// Where the JSON Schema goes
+		#[arg(long, default_value = "protocol.schema.json")]
+		schema: PathBuf,
+
+		/// Where the TypeScript definitions go
+		#[arg(long, default_value = "frontend/src/protocol.ts")]
+		typescript: PathBuf,
+
+		/// Fail instead of writing when the files are out of date
+		#[arg(long)]
+		check: bool,
+	},
+}
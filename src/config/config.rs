@@ -0,0 +1,249 @@
+use std::{env, fs, net::SocketAddr, path::Path, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde_derive::Deserialize;
+
+use super::arguments::Arguments;
+
+const DEFAULT_CONFIG_PATH: &str = "ucv.toml";
+
+pub type ConfigAccess = Arc<ServerConfig>;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+	pub listen_address: SocketAddr,
+	pub database_backend: DatabaseBackend,
+	pub database_path: String,
+	pub worker_threads: usize,
+	pub log_filter: String,
+	pub max_player_name_length: usize,
+	pub max_message_size: usize,
+	pub gamemaster_inbox_size: usize,
+	pub client_outbox_size: usize,
+	pub slow_client_policy: SlowClientPolicy,
+	pub slow_client_lag_threshold: usize,
+	pub points_per_win: usize,
+	pub shutdown_grace_period: u64,
+	pub session_lifetime: u64,
+}
+
+// Where the game is stored
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBackend {
+	// The SQLite file at database_path
+	Sqlite,
+	// Nothing is kept after the server stops, organizers are copied from database_path
+	Memory,
+}
+
+// What the gamemaster does when a client's outbox is full
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SlowClientPolicy {
+	// Drop the message and keep the client
+	Drop,
+	// Drop the message, and disconnect the client once it lags behind the threshold
+	Disconnect,
+}
+
+impl Default for ServerConfig {
+	fn default() -> ServerConfig {
+		ServerConfig {
+			listen_address: SocketAddr::from(([0, 0, 0, 0], 9002)),
+			database_backend: DatabaseBackend::Sqlite,
+			database_path: "database.db".to_owned(),
+			worker_threads: 8,
+			log_filter: "debug".to_owned(),
+			max_player_name_length: 12,
+			max_message_size: 64 * 1024,
+			gamemaster_inbox_size: 1024,
+			client_outbox_size: 64,
+			slow_client_policy: SlowClientPolicy::Disconnect,
+			slow_client_lag_threshold: 32,
+			points_per_win: 1,
+			shutdown_grace_period: 5,
+			session_lifetime: 6 * 60 * 60,
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+	listen_address: Option<SocketAddr>,
+	database_backend: Option<DatabaseBackend>,
+	database_path: Option<String>,
+	worker_threads: Option<usize>,
+	log_filter: Option<String>,
+	max_player_name_length: Option<usize>,
+	max_message_size: Option<usize>,
+	gamemaster_inbox_size: Option<usize>,
+	client_outbox_size: Option<usize>,
+	slow_client_policy: Option<SlowClientPolicy>,
+	slow_client_lag_threshold: Option<usize>,
+	points_per_win: Option<usize>,
+	shutdown_grace_period: Option<u64>,
+	session_lifetime: Option<u64>,
+}
+
+impl ServerConfig {
+	// Defaults, then the config file, then environment variables, then command line flags
+	pub fn load(arguments: &Arguments) -> Result<ServerConfig> {
+		let mut config = ServerConfig::default();
+
+		let config_file = match &arguments.config {
+			Some(path) => Some(read_config_file(path)?),
+			None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+				Some(read_config_file(Path::new(DEFAULT_CONFIG_PATH))?)
+			}
+			None => None,
+		};
+		if let Some(config_file) = config_file {
+			config.apply_file(config_file);
+		}
+
+		// The usual env_logger variable still works, UCV_LOG_FILTER and --log-filter win over it
+		if let Ok(rust_log) = env::var("RUST_LOG") {
+			if !rust_log.trim().is_empty() {
+				config.log_filter = rust_log;
+			}
+		}
+
+		config.apply_arguments(arguments);
+		config.validate()?;
+
+		Ok(config)
+	}
+
+	fn apply_file(&mut self, file: ConfigFile) {
+		if let Some(listen_address) = file.listen_address {
+			self.listen_address = listen_address;
+		}
+		if let Some(database_backend) = file.database_backend {
+			self.database_backend = database_backend;
+		}
+		if let Some(database_path) = file.database_path {
+			self.database_path = database_path;
+		}
+		if let Some(worker_threads) = file.worker_threads {
+			self.worker_threads = worker_threads;
+		}
+		if let Some(log_filter) = file.log_filter {
+			self.log_filter = log_filter;
+		}
+		if let Some(max_player_name_length) = file.max_player_name_length {
+			self.max_player_name_length = max_player_name_length;
+		}
+		if let Some(max_message_size) = file.max_message_size {
+			self.max_message_size = max_message_size;
+		}
+		if let Some(gamemaster_inbox_size) = file.gamemaster_inbox_size {
+			self.gamemaster_inbox_size = gamemaster_inbox_size;
+		}
+		if let Some(client_outbox_size) = file.client_outbox_size {
+			self.client_outbox_size = client_outbox_size;
+		}
+		if let Some(slow_client_policy) = file.slow_client_policy {
+			self.slow_client_policy = slow_client_policy;
+		}
+		if let Some(slow_client_lag_threshold) = file.slow_client_lag_threshold {
+			self.slow_client_lag_threshold = slow_client_lag_threshold;
+		}
+		if let Some(points_per_win) = file.points_per_win {
+			self.points_per_win = points_per_win;
+		}
+		if let Some(shutdown_grace_period) = file.shutdown_grace_period {
+			self.shutdown_grace_period = shutdown_grace_period;
+		}
+		if let Some(session_lifetime) = file.session_lifetime {
+			self.session_lifetime = session_lifetime;
+		}
+	}
+
+	fn apply_arguments(&mut self, arguments: &Arguments) {
+		if let Some(listen_address) = arguments.listen_address {
+			self.listen_address = listen_address;
+		}
+		if let Some(database_backend) = arguments.database_backend {
+			self.database_backend = database_backend;
+		}
+		if let Some(database_path) = &arguments.database_path {
+			self.database_path = database_path.clone();
+		}
+		if let Some(worker_threads) = arguments.worker_threads {
+			self.worker_threads = worker_threads;
+		}
+		if let Some(log_filter) = &arguments.log_filter {
+			self.log_filter = log_filter.clone();
+		}
+		if let Some(max_player_name_length) = arguments.max_player_name_length {
+			self.max_player_name_length = max_player_name_length;
+		}
+		if let Some(max_message_size) = arguments.max_message_size {
+			self.max_message_size = max_message_size;
+		}
+		if let Some(gamemaster_inbox_size) = arguments.gamemaster_inbox_size {
+			self.gamemaster_inbox_size = gamemaster_inbox_size;
+		}
+		if let Some(client_outbox_size) = arguments.client_outbox_size {
+			self.client_outbox_size = client_outbox_size;
+		}
+		if let Some(slow_client_policy) = arguments.slow_client_policy {
+			self.slow_client_policy = slow_client_policy;
+		}
+		if let Some(slow_client_lag_threshold) = arguments.slow_client_lag_threshold {
+			self.slow_client_lag_threshold = slow_client_lag_threshold;
+		}
+		if let Some(points_per_win) = arguments.points_per_win {
+			self.points_per_win = points_per_win;
+		}
+		if let Some(shutdown_grace_period) = arguments.shutdown_grace_period {
+			self.shutdown_grace_period = shutdown_grace_period;
+		}
+		if let Some(session_lifetime) = arguments.session_lifetime {
+			self.session_lifetime = session_lifetime;
+		}
+	}
+
+	fn validate(&self) -> Result<()> {
+		if self.database_path.trim().is_empty() {
+			bail!("database_path must not be empty");
+		}
+		if self.worker_threads == 0 {
+			bail!("worker_threads must be at least 1");
+		}
+		if self.log_filter.trim().is_empty() {
+			bail!("log_filter must not be empty");
+		}
+		if self.max_player_name_length == 0 {
+			bail!("max_player_name_length must be at least 1");
+		}
+		if self.max_message_size == 0 {
+			bail!("max_message_size must be at least 1 byte");
+		}
+		if self.gamemaster_inbox_size == 0 {
+			bail!("gamemaster_inbox_size must be at least 1");
+		}
+		if self.client_outbox_size == 0 {
+			bail!("client_outbox_size must be at least 1");
+		}
+		if self.slow_client_lag_threshold == 0 {
+			bail!("slow_client_lag_threshold must be at least 1");
+		}
+		if self.session_lifetime == 0 {
+			bail!("session_lifetime must be at least 1 second");
+		}
+
+		Ok(())
+	}
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile> {
+	let contents = fs::read_to_string(path)
+		.with_context(|| format!("Could not read config file {}", path.display()))?;
+
+	toml::from_str(&contents)
+		.with_context(|| format!("Could not parse config file {}", path.display()))
+}
@@ -0,0 +1,3 @@
+pub mod arguments;
+#[allow(clippy::module_inception)]
+pub mod config;
@@ -2,54 +2,369 @@ use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 
-use crate::gamemaster::types::{Choice, ChoiceOption, ChoicesMap, Player, Round, RoundState};
+use crate::gamemaster::types::{
+	Choice, ChoiceEvent, ChoiceOption, ChoicesMap, Game, Organizer, OrganizerAccount, PlannedRound,
+	Player, Room, Round, RoundState,
+};
 
 pub type DatabaseAccess = Arc<Mutex<dyn Database>>;
 
+// Every room starts out with this game, also holding what was played before games
+pub const FIRST_GAME_NAME: &str = "Game 1";
+
 pub trait Database: Send {
 	fn initialize_database(&self) -> Result<()>;
+	fn close(&mut self) -> Result<()>;
+
+	// Transactions nest, an inner rollback only undoes the inner work.
+	// Prefer transaction() over calling these directly.
+	fn begin_transaction(&self) -> Result<()>;
+	fn commit_transaction(&self) -> Result<()>;
+	fn rollback_transaction(&self) -> Result<()>;
+	// Undoes every transaction still open, like the ones a panic left behind
+	fn rollback_open_transactions(&self) -> Result<()>;
+
+	fn get_rooms(&self) -> Result<Vec<Room>>;
+	fn find_room_by_id(&self, id: i64) -> Result<Option<Room>>;
+	fn find_room_by_code(&self, code: &str) -> Result<Option<Room>>;
+	// Also starts the first game of the room
+	fn create_room(&self, code: &str, name: &str) -> Result<Room>;
 
-	fn find_player_by_id(&self, id: u8) -> Result<Option<Player>>;
-	fn find_player_by_name(&self, name: &str) -> Result<Option<Player>>;
-	fn create_player(&self, name: &str) -> Result<Player>;
-	fn find_or_create_player(&self, name: &str) -> Result<Player>;
-	fn mark_player(&self, id: u8, points: Option<usize>, can_vote: Option<bool>) -> Result<Player>;
+	// Rounds, choices and points belong to the current game of a room
+	fn get_current_game(&self, room_id: i64) -> Result<Game>;
+	fn get_games(&self, room_id: i64) -> Result<Vec<Game>>;
+	// Archives the current game and makes the new one current.
+	// Nobody can vote until the first round of the new game.
+	fn start_game(&self, room_id: i64, name: &str) -> Result<Game>;
+
+	// Players belong to one room, the same name can play in several rooms
+	fn find_player_by_id(&self, room_id: i64, id: i64) -> Result<Option<Player>>;
+	fn find_player_by_name(&self, room_id: i64, name: &str) -> Result<Option<Player>>;
+	fn create_player(&self, room_id: i64, name: &str) -> Result<Player>;
+	fn find_or_create_player(&self, room_id: i64, name: &str) -> Result<Player>;
+	fn mark_player(
+		&self,
+		room_id: i64,
+		id: i64,
+		points: Option<usize>,
+		can_vote: Option<bool>,
+	) -> Result<Player>;
 
-	fn get_active_round(&self) -> Result<Option<Round>>;
-	fn find_round_by_number_and_phase(&self, number: u8, phase: u8) -> Result<Option<Round>>;
+	fn find_organizer_by_username(&self, username: &str) -> Result<Option<OrganizerAccount>>;
+	fn create_organizer(
+		&self,
+		username: &str,
+		password_hash: &str,
+		name: &str,
+	) -> Result<Organizer>;
+
+	fn get_active_round(&self, room_id: i64) -> Result<Option<Round>>;
+	fn find_round_by_number_and_phase(
+		&self,
+		room_id: i64,
+		number: u8,
+		phase: u8,
+	) -> Result<Option<Round>>;
+	// Any round of any game of the room, archived ones included
+	fn find_round_in_room(&self, room_id: i64, round_id: i64) -> Result<Option<Round>>;
 	fn create_round(
 		&self,
+		room_id: i64,
 		number: u8,
 		phase: u8,
 		state: RoundState,
 		question: String,
-		choice_a: String,
-		choice_b: String,
+		options: Vec<String>,
 	) -> Result<Round>;
 	fn update_round(
 		&self,
+		room_id: i64,
 		number: u8,
 		phase: u8,
 		state: Option<RoundState>,
 		question: Option<String>,
-		choice_a: Option<String>,
-		choice_b: Option<String>,
+		options: Option<Vec<String>>,
 	) -> Result<Round>;
+	// False if the round was resolved before, its points are only awarded once
+	fn mark_round_resolved(&self, round_id: i64) -> Result<bool>;
+	fn is_round_resolved(&self, round_id: i64) -> Result<bool>;
 
-	fn find_choice_by_round_and_player(
+	// Replaces any plan loaded before
+	fn replace_game_plan(&self, room_id: i64, plan: &[PlannedRound]) -> Result<()>;
+	fn get_game_plan(&self, room_id: i64) -> Result<Vec<PlannedRound>>;
+	fn find_planned_round(
 		&self,
-		round_id: u8,
-		player_id: u8,
-	) -> Result<Option<Choice>>;
+		room_id: i64,
+		number: u8,
+		phase: u8,
+	) -> Result<Option<PlannedRound>>;
+
+	// Also records the vote in the history of the player's choice
 	fn update_or_create_choice(
 		&self,
-		round_id: u8,
-		player_id: u8,
+		round_id: i64,
+		player_id: i64,
 		choice: ChoiceOption,
 	) -> Result<Choice>;
-	fn mark_choice(&self, choice_id: u8, lie: Option<bool>) -> Result<()>;
+	// Oldest first
+	fn get_choice_events(&self, round_id: i64, player_id: i64) -> Result<Vec<ChoiceEvent>>;
+	fn mark_choice(&self, choice_id: i64, lie: Option<bool>) -> Result<()>;
+
+	fn get_choices_by_round_id(&self, round_id: i64) -> Result<ChoicesMap>;
+
+	fn check_player_is_allowed_to_vote(&self, player_id: i64) -> Result<bool>;
+}
+
+// Runs the work as one unit, either all of its changes are kept or none
+pub fn transaction<T>(
+	database: &dyn Database,
+	work: impl FnOnce(&dyn Database) -> Result<T>,
+) -> Result<T> {
+	database.begin_transaction()?;
+
+	match work(database) {
+		Ok(value) => {
+			database.commit_transaction()?;
+			Ok(value)
+		}
+		Err(error) => {
+			database.rollback_transaction()?;
+			Err(error)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use anyhow::bail;
+
+	use super::*;
+	use crate::{
+		database::{memory::InMemoryDatabase, sqlite::SqliteDatabase},
+		gamemaster::types::DEFAULT_ROOM_ID,
+	};
+
+	// Runs the same calls against a backend and writes down everything it answered.
+	// Timestamps differ between runs, so only their order and presence are kept.
+	fn play(database: &dyn Database) -> Result<Vec<String>> {
+		let mut log = vec![];
+
+		let room = database.create_room("ABCDEF", "Test room")?;
+		log.push(format!("{:?}", room));
+		log.push(format!("{:?}", database.find_room_by_code("ABCDEF")?));
+		log.push(format!("{:?}", database.find_room_by_id(room.id)?));
+		log.push(format!(
+			"duplicate code: {}",
+			database.create_room("ABCDEF", "Again").is_err()
+		));
+		log.push(format!("{:?}", database.get_current_game(room.id)?.name));
+
+		// Names are unique within a room only
+		let alpha = database.create_player(room.id, "alpha")?;
+		let bravo = database.find_or_create_player(room.id, "bravo")?;
+		let charlie = database.find_or_create_player(room.id, "charlie")?;
+		log.push(format!(
+			"{:?}",
+			database.find_or_create_player(room.id, "alpha")?
+		));
+		log.push(format!(
+			"duplicate name: {}",
+			database.create_player(room.id, "alpha").is_err()
+		));
+		log.push(format!(
+			"{:?}",
+			database.create_player(DEFAULT_ROOM_ID, "alpha")?
+		));
+		log.push(format!(
+			"{:?}",
+			database.find_player_by_id(DEFAULT_ROOM_ID, bravo.id)?
+		));
+		log.push(format!(
+			"{:?}",
+			database.find_player_by_name(room.id, "charlie")?
+		));
+
+		// The active round is the latest by number and phase, not the last one created
+		let options = vec!["Cats".to_owned(), "Dogs".to_owned(), "Birds".to_owned()];
+		let first = database.create_round(
+			room.id,
+			1,
+			1,
+			RoundState::Standby,
+			"Pets?".to_owned(),
+			options.clone(),
+		)?;
+		database.create_round(
+			room.id,
+			2,
+			1,
+			RoundState::Standby,
+			"Later".to_owned(),
+			options.clone(),
+		)?;
+		database.create_round(
+			room.id,
+			1,
+			2,
+			RoundState::Standby,
+			"Sooner".to_owned(),
+			options,
+		)?;
+		log.push(format!("{:?}", database.get_active_round(room.id)?));
+		log.push(format!("{:?}", database.get_active_round(DEFAULT_ROOM_ID)?));
+		log.push(format!(
+			"duplicate round: {}",
+			database
+				.create_round(
+					room.id,
+					1,
+					1,
+					RoundState::Standby,
+					"Again".to_owned(),
+					vec!["A".to_owned(), "B".to_owned()],
+				)
+				.is_err()
+		));
+		log.push(format!(
+			"{:?}",
+			database.update_round(
+				room.id,
+				1,
+				1,
+				Some(RoundState::VotingTime),
+				None,
+				Some(vec!["Cats".to_owned(), "Dogs".to_owned()]),
+			)?
+		));
+		log.push(format!(
+			"missing round: {}",
+			database
+				.update_round(room.id, 9, 9, None, None, None)
+				.is_err()
+		));
+		log.push(format!(
+			"{:?}",
+			database.find_round_in_room(room.id, first.id)?
+		));
+		log.push(format!(
+			"{:?}",
+			database.find_round_in_room(DEFAULT_ROOM_ID, first.id)?
+		));
+
+		// A changed vote replaces the choice and adds to its history
+		database.update_or_create_choice(first.id, alpha.id, ChoiceOption(0))?;
+		let lie = database.update_or_create_choice(first.id, bravo.id, ChoiceOption(1))?;
+		database.update_or_create_choice(first.id, charlie.id, ChoiceOption(1))?;
+		log.push(format!(
+			"{:?}",
+			database.update_or_create_choice(first.id, alpha.id, ChoiceOption(1))?
+		));
+		database.mark_choice(lie.id, Some(true))?;
+		let mut choices: Vec<(i64, Choice)> = database
+			.get_choices_by_round_id(first.id)?
+			.into_iter()
+			.collect();
+		choices.sort_by_key(|(player_id, _)| *player_id);
+		log.push(format!("{:?}", choices));
+		let events = database.get_choice_events(first.id, alpha.id)?;
+		log.push(format!(
+			"{:?}",
+			events.iter().map(|event| event.option).collect::<Vec<_>>()
+		));
+		log.push(format!(
+			"events in order: {}",
+			events
+				.windows(2)
+				.all(|pair| pair[0].submitted_at <= pair[1].submitted_at)
+		));
+
+		// Points are only awarded once per round
+		log.push(format!("{:?}", database.mark_round_resolved(first.id)?));
+		log.push(format!("{:?}", database.mark_round_resolved(first.id)?));
+		log.push(format!("{:?}", database.mark_round_resolved(-1)?));
+		log.push(format!("{:?}", database.is_round_resolved(first.id)?));
+		log.push(format!("{:?}", database.is_round_resolved(-1)?));
+
+		log.push(format!(
+			"{:?}",
+			database.mark_player(room.id, alpha.id, Some(2), None)?
+		));
+		log.push(format!(
+			"{:?}",
+			database.mark_player(room.id, bravo.id, None, Some(true))?
+		));
+		log.push(format!(
+			"{:?}",
+			database.check_player_is_allowed_to_vote(bravo.id)?
+		));
+		log.push(format!(
+			"{:?}",
+			database.check_player_is_allowed_to_vote(charlie.id)?
+		));
+
+		// A failed unit of work leaves nothing behind
+		let undone: Result<()> = transaction(database, |database| {
+			database.mark_player(room.id, charlie.id, Some(5), Some(true))?;
+			database.create_player(room.id, "delta")?;
+			bail!("Undo");
+		});
+		log.push(format!("undone: {}", undone.is_err()));
+		log.push(format!(
+			"{:?}",
+			database.find_player_by_id(room.id, charlie.id)?
+		));
+		log.push(format!(
+			"{:?}",
+			database.find_player_by_name(room.id, "delta")?
+		));
+
+		let plan = vec![PlannedRound {
+			number: 1,
+			phase: 1,
+			question: "Planned?".to_owned(),
+			options: vec!["Yes".to_owned(), "No".to_owned(), "Maybe".to_owned()],
+			voting_seconds: Some(30),
+		}];
+		database.replace_game_plan(room.id, &plan)?;
+		log.push(format!("{:?}", database.get_game_plan(room.id)?));
+		log.push(format!("{:?}", database.find_planned_round(room.id, 1, 1)?));
+		log.push(format!("{:?}", database.get_game_plan(DEFAULT_ROOM_ID)?));
+
+		// A new game starts from scratch, the old one keeps its rounds
+		let game = database.start_game(room.id, "Game 2")?;
+		log.push(format!("{:?} {:?}", game.name, game.archived_at));
+		log.push(format!(
+			"{:?}",
+			database.find_player_by_id(room.id, alpha.id)?
+		));
+		log.push(format!("{:?}", database.get_active_round(room.id)?));
+		log.push(format!(
+			"{:?}",
+			database.find_round_in_room(room.id, first.id)?
+		));
+		let games = database.get_games(room.id)?;
+		log.push(format!(
+			"{:?}",
+			games
+				.iter()
+				.map(|game| (&game.name, game.archived_at.is_some()))
+				.collect::<Vec<_>>()
+		));
+		log.push(format!("{:?}", database.get_game_plan(room.id)?.len()));
+
+		Ok(log)
+	}
+
+	#[test]
+	fn both_backends_answer_alike() -> Result<()> {
+		let sqlite = play(&SqliteDatabase::new(":memory:"))?;
+		let memory = play(&InMemoryDatabase::new())?;
 
-	fn get_choices_by_round_id(&self, round_id: u8) -> Result<ChoicesMap>;
+		for (sqlite_line, memory_line) in sqlite.iter().zip(memory.iter()) {
+			assert_eq!(sqlite_line, memory_line);
+		}
+		assert_eq!(sqlite.len(), memory.len());
 
-	fn check_player_is_allowed_to_vote(&self, player_id: u8) -> Result<bool>;
+		Ok(())
+	}
 }
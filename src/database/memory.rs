@@ -0,0 +1,702 @@
+use std::cell::{RefCell, RefMut};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+
+use crate::gamemaster::types::{
+	Choice, ChoiceEvent, ChoiceOption, ChoicesMap, Game, Organizer, OrganizerAccount, PlannedRound,
+	Player, Room, Round, RoundState, DEFAULT_ROOM_ID,
+};
+
+use super::database::{Database, FIRST_GAME_NAME};
+
+// Keeps everything in memory and forgets it when the server stops.
+// Behaves like SqliteDatabase, down to the unique names and the generated ids.
+pub struct InMemoryDatabase {
+	// Taken out when the database is closed
+	tables: Option<RefCell<Tables>>,
+	// The tables as they were when each open transaction began
+	savepoints: RefCell<Vec<Tables>>,
+}
+
+#[derive(Clone, Default)]
+struct Tables {
+	rooms: Vec<Room>,
+	games: Vec<GameRow>,
+	players: Vec<PlayerRow>,
+	scores: Vec<ScoreRow>,
+	organizers: Vec<OrganizerAccount>,
+	rounds: Vec<RoundRow>,
+	planned_rounds: Vec<PlannedRoundRow>,
+	choices: Vec<ChoiceRow>,
+	choice_events: Vec<ChoiceEventRow>,
+	// Like AUTOINCREMENT, ids are never handed out twice
+	last_room_id: i64,
+	last_game_id: i64,
+	last_player_id: i64,
+	last_organizer_id: i64,
+	last_round_id: i64,
+	last_choice_id: i64,
+}
+
+// The current game of a room is the one that is not archived yet
+#[derive(Clone)]
+struct GameRow {
+	room_id: i64,
+	game: Game,
+}
+
+#[derive(Clone)]
+struct PlayerRow {
+	room_id: i64,
+	id: i64,
+	name: String,
+	can_vote: bool,
+}
+
+#[derive(Clone)]
+struct ScoreRow {
+	game_id: i64,
+	player_id: i64,
+	points: usize,
+}
+
+#[derive(Clone)]
+struct RoundRow {
+	game_id: i64,
+	round: Round,
+	resolved: bool,
+}
+
+#[derive(Clone)]
+struct PlannedRoundRow {
+	room_id: i64,
+	planned_round: PlannedRound,
+}
+
+#[derive(Clone)]
+struct ChoiceRow {
+	round_id: i64,
+	player_id: i64,
+	choice: Choice,
+}
+
+#[derive(Clone)]
+struct ChoiceEventRow {
+	round_id: i64,
+	player_id: i64,
+	event: ChoiceEvent,
+}
+
+impl Tables {
+	fn current_game_id(&self, room_id: i64) -> Result<i64> {
+		self.games
+			.iter()
+			.find(|row| row.room_id == room_id && row.game.archived_at.is_none())
+			.map(|row| row.game.id)
+			.ok_or(anyhow!("Room {} has no current game", room_id))
+	}
+
+	fn insert_game(&mut self, room_id: i64, name: &str) -> Game {
+		self.last_game_id += 1;
+		let game = Game {
+			id: self.last_game_id,
+			name: name.to_owned(),
+			started_at: Utc::now().timestamp_millis(),
+			archived_at: None,
+		};
+		self.games.push(GameRow {
+			room_id,
+			game: game.clone(),
+		});
+
+		game
+	}
+
+	// With the points of the current game of its room
+	fn to_player(&self, row: &PlayerRow) -> Player {
+		let points = self.current_game_id(row.room_id).ok().and_then(|game_id| {
+			self.scores
+				.iter()
+				.find(|score| score.game_id == game_id && score.player_id == row.id)
+				.map(|score| score.points)
+		});
+
+		Player {
+			id: row.id,
+			name: row.name.clone(),
+			points: Some(points.unwrap_or(0)),
+			can_vote: Some(row.can_vote),
+		}
+	}
+}
+
+impl InMemoryDatabase {
+	pub fn new() -> Self {
+		let new = Self {
+			tables: Some(RefCell::new(Tables::default())),
+			savepoints: RefCell::new(Vec::new()),
+		};
+		new.initialize_database().unwrap();
+
+		new
+	}
+
+	fn tables(&self) -> Result<RefMut<'_, Tables>> {
+		match &self.tables {
+			Some(tables) => Ok(tables.borrow_mut()),
+			None => bail!("The database is closed"),
+		}
+	}
+}
+
+impl Default for InMemoryDatabase {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Database for InMemoryDatabase {
+	fn close(&mut self) -> Result<()> {
+		self.tables = None;
+
+		Ok(())
+	}
+
+	fn initialize_database(&self) -> Result<()> {
+		let mut tables = self.tables()?;
+
+		if tables.rooms.is_empty() {
+			tables.rooms.push(Room {
+				id: DEFAULT_ROOM_ID,
+				code: "MAIN".to_owned(),
+				name: "Main room".to_owned(),
+			});
+			tables.last_room_id = DEFAULT_ROOM_ID;
+			tables.insert_game(DEFAULT_ROOM_ID, FIRST_GAME_NAME);
+		}
+
+		Ok(())
+	}
+
+	fn begin_transaction(&self) -> Result<()> {
+		let snapshot = self.tables()?.clone();
+		self.savepoints.borrow_mut().push(snapshot);
+
+		Ok(())
+	}
+
+	fn commit_transaction(&self) -> Result<()> {
+		if self.savepoints.borrow_mut().pop().is_none() {
+			bail!("There is no transaction to commit");
+		}
+
+		Ok(())
+	}
+
+	fn rollback_transaction(&self) -> Result<()> {
+		let Some(snapshot) = self.savepoints.borrow_mut().pop() else {
+			bail!("There is no transaction to roll back");
+		};
+		*self.tables()? = snapshot;
+
+		Ok(())
+	}
+
+	fn rollback_open_transactions(&self) -> Result<()> {
+		// The oldest snapshot is from before the outermost transaction
+		let snapshot = self.savepoints.borrow_mut().drain(..).next();
+		if let Some(snapshot) = snapshot {
+			*self.tables()? = snapshot;
+		}
+
+		Ok(())
+	}
+
+	fn get_rooms(&self) -> Result<Vec<Room>> {
+		Ok(self.tables()?.rooms.clone())
+	}
+
+	fn find_room_by_id(&self, id: i64) -> Result<Option<Room>> {
+		let tables = self.tables()?;
+
+		Ok(tables.rooms.iter().find(|room| room.id == id).cloned())
+	}
+
+	fn find_room_by_code(&self, code: &str) -> Result<Option<Room>> {
+		let tables = self.tables()?;
+
+		Ok(tables.rooms.iter().find(|room| room.code == code).cloned())
+	}
+
+	fn create_room(&self, code: &str, name: &str) -> Result<Room> {
+		let mut tables = self.tables()?;
+		if tables.rooms.iter().any(|room| room.code == code) {
+			bail!("A room with the code {} already exists", code);
+		}
+
+		tables.last_room_id += 1;
+		let room = Room {
+			id: tables.last_room_id,
+			code: code.to_owned(),
+			name: name.to_owned(),
+		};
+		tables.rooms.push(room.clone());
+		tables.insert_game(room.id, FIRST_GAME_NAME);
+
+		Ok(room)
+	}
+
+	fn get_current_game(&self, room_id: i64) -> Result<Game> {
+		let tables = self.tables()?;
+		let game_id = tables.current_game_id(room_id)?;
+
+		tables
+			.games
+			.iter()
+			.find(|row| row.game.id == game_id)
+			.map(|row| row.game.clone())
+			.ok_or(anyhow!("Room {} has no current game", room_id))
+	}
+
+	fn get_games(&self, room_id: i64) -> Result<Vec<Game>> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.games
+			.iter()
+			.filter(|row| row.room_id == room_id)
+			.map(|row| row.game.clone())
+			.collect())
+	}
+
+	fn start_game(&self, room_id: i64, name: &str) -> Result<Game> {
+		let mut tables = self.tables()?;
+		if !tables.rooms.iter().any(|room| room.id == room_id) {
+			bail!("Could not find room {}", room_id);
+		}
+
+		let archived_at = Utc::now().timestamp_millis();
+		for row in tables.games.iter_mut() {
+			if row.room_id == room_id && row.game.archived_at.is_none() {
+				row.game.archived_at = Some(archived_at);
+			}
+		}
+		// Players are let back in by the first round of the new game
+		for row in tables.players.iter_mut() {
+			if row.room_id == room_id {
+				row.can_vote = false;
+			}
+		}
+
+		Ok(tables.insert_game(room_id, name))
+	}
+
+	fn find_player_by_id(&self, room_id: i64, id: i64) -> Result<Option<Player>> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.players
+			.iter()
+			.find(|row| row.room_id == room_id && row.id == id)
+			.map(|row| tables.to_player(row)))
+	}
+
+	fn find_player_by_name(&self, room_id: i64, name: &str) -> Result<Option<Player>> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.players
+			.iter()
+			.find(|row| row.room_id == room_id && row.name == name)
+			.map(|row| tables.to_player(row)))
+	}
+
+	fn create_player(&self, room_id: i64, name: &str) -> Result<Player> {
+		let mut tables = self.tables()?;
+		if tables
+			.players
+			.iter()
+			.any(|row| row.room_id == room_id && row.name == name)
+		{
+			bail!("A player named {} already exists in this room", name);
+		}
+
+		tables.last_player_id += 1;
+		let row = PlayerRow {
+			room_id,
+			id: tables.last_player_id,
+			name: name.to_owned(),
+			can_vote: false,
+		};
+		let player = tables.to_player(&row);
+		tables.players.push(row);
+
+		Ok(player)
+	}
+
+	fn find_or_create_player(&self, room_id: i64, name: &str) -> Result<Player> {
+		match self.find_player_by_name(room_id, name)? {
+			Some(player) => Ok(player),
+			None => self.create_player(room_id, name),
+		}
+	}
+
+	fn mark_player(
+		&self,
+		room_id: i64,
+		id: i64,
+		points: Option<usize>,
+		can_vote: Option<bool>,
+	) -> Result<Player> {
+		if points.is_none() && can_vote.is_none() {
+			bail!("Could not update the player");
+		}
+
+		let mut tables = self.tables()?;
+		let Some(row) = tables
+			.players
+			.iter_mut()
+			.find(|row| row.room_id == room_id && row.id == id)
+		else {
+			bail!("Could not update the player");
+		};
+
+		if let Some(can_vote) = can_vote {
+			row.can_vote = can_vote;
+		}
+		let row = row.clone();
+
+		// Points count towards the current game only
+		if let Some(points) = points {
+			let game_id = tables.current_game_id(room_id)?;
+			match tables
+				.scores
+				.iter_mut()
+				.find(|score| score.game_id == game_id && score.player_id == id)
+			{
+				Some(score) => score.points = points,
+				None => tables.scores.push(ScoreRow {
+					game_id,
+					player_id: id,
+					points,
+				}),
+			}
+		}
+
+		Ok(tables.to_player(&row))
+	}
+
+	fn find_organizer_by_username(&self, username: &str) -> Result<Option<OrganizerAccount>> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.organizers
+			.iter()
+			.find(|account| account.organizer.username == username)
+			.cloned())
+	}
+
+	fn create_organizer(
+		&self,
+		username: &str,
+		password_hash: &str,
+		name: &str,
+	) -> Result<Organizer> {
+		let mut tables = self.tables()?;
+		if tables
+			.organizers
+			.iter()
+			.any(|account| account.organizer.username == username)
+		{
+			bail!("An organizer named {} already exists", username);
+		}
+
+		tables.last_organizer_id += 1;
+		let organizer = Organizer {
+			id: tables.last_organizer_id,
+			username: username.to_owned(),
+			name: name.to_owned(),
+		};
+		tables.organizers.push(OrganizerAccount {
+			organizer: organizer.clone(),
+			password_hash: password_hash.to_owned(),
+		});
+
+		Ok(organizer)
+	}
+
+	// The round with the highest number and phase, like SqliteDatabase
+	fn get_active_round(&self, room_id: i64) -> Result<Option<Round>> {
+		let tables = self.tables()?;
+		let game_id = tables.current_game_id(room_id)?;
+
+		Ok(tables
+			.rounds
+			.iter()
+			.filter(|row| row.game_id == game_id)
+			.max_by_key(|row| (row.round.number, row.round.phase))
+			.map(|row| row.round.clone()))
+	}
+
+	fn find_round_by_number_and_phase(
+		&self,
+		room_id: i64,
+		number: u8,
+		phase: u8,
+	) -> Result<Option<Round>> {
+		let tables = self.tables()?;
+		let game_id = tables.current_game_id(room_id)?;
+
+		Ok(tables
+			.rounds
+			.iter()
+			.find(|row| {
+				row.game_id == game_id && row.round.number == number && row.round.phase == phase
+			})
+			.map(|row| row.round.clone()))
+	}
+
+	fn find_round_in_room(&self, room_id: i64, round_id: i64) -> Result<Option<Round>> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.rounds
+			.iter()
+			.find(|row| {
+				row.round.id == round_id
+					&& tables
+						.games
+						.iter()
+						.any(|game| game.game.id == row.game_id && game.room_id == room_id)
+			})
+			.map(|row| row.round.clone()))
+	}
+
+	fn create_round(
+		&self,
+		room_id: i64,
+		number: u8,
+		phase: u8,
+		state: RoundState,
+		question: String,
+		options: Vec<String>,
+	) -> Result<Round> {
+		let mut tables = self.tables()?;
+		let game_id = tables.current_game_id(room_id)?;
+		if tables.rounds.iter().any(|row| {
+			row.game_id == game_id && row.round.number == number && row.round.phase == phase
+		}) {
+			bail!("Round {}-{} already exists", number, phase);
+		}
+
+		tables.last_round_id += 1;
+		let round = Round {
+			id: tables.last_round_id,
+			number,
+			phase,
+			state,
+			question,
+			options,
+		};
+		tables.rounds.push(RoundRow {
+			game_id,
+			round: round.clone(),
+			resolved: false,
+		});
+
+		Ok(round)
+	}
+
+	fn update_round(
+		&self,
+		room_id: i64,
+		number: u8,
+		phase: u8,
+		state: Option<RoundState>,
+		question: Option<String>,
+		options: Option<Vec<String>>,
+	) -> Result<Round> {
+		let mut tables = self.tables()?;
+		let game_id = tables.current_game_id(room_id)?;
+		let Some(row) = tables.rounds.iter_mut().find(|row| {
+			row.game_id == game_id && row.round.number == number && row.round.phase == phase
+		}) else {
+			bail!("Could not find round");
+		};
+
+		if let Some(new_state) = state {
+			row.round.state = new_state;
+		}
+		if let Some(new_question) = question {
+			row.round.question = new_question;
+		}
+		if let Some(new_options) = options {
+			row.round.options = new_options;
+		}
+
+		Ok(row.round.clone())
+	}
+
+	fn mark_round_resolved(&self, round_id: i64) -> Result<bool> {
+		let mut tables = self.tables()?;
+		// Like the UPDATE of the SQLite backend, a missing round changes nothing
+		let Some(row) = tables
+			.rounds
+			.iter_mut()
+			.find(|row| row.round.id == round_id)
+		else {
+			return Ok(false);
+		};
+
+		let was_resolved = row.resolved;
+		row.resolved = true;
+
+		Ok(!was_resolved)
+	}
+
+	fn is_round_resolved(&self, round_id: i64) -> Result<bool> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.rounds
+			.iter()
+			.any(|row| row.round.id == round_id && row.resolved))
+	}
+
+	fn replace_game_plan(&self, room_id: i64, plan: &[PlannedRound]) -> Result<()> {
+		let mut tables = self.tables()?;
+
+		tables.planned_rounds.retain(|row| row.room_id != room_id);
+		tables
+			.planned_rounds
+			.extend(plan.iter().map(|planned_round| PlannedRoundRow {
+				room_id,
+				planned_round: planned_round.clone(),
+			}));
+
+		Ok(())
+	}
+
+	fn get_game_plan(&self, room_id: i64) -> Result<Vec<PlannedRound>> {
+		let tables = self.tables()?;
+
+		let mut plan: Vec<PlannedRound> = tables
+			.planned_rounds
+			.iter()
+			.filter(|row| row.room_id == room_id)
+			.map(|row| row.planned_round.clone())
+			.collect();
+		plan.sort_by_key(|planned_round| (planned_round.number, planned_round.phase));
+
+		Ok(plan)
+	}
+
+	fn find_planned_round(
+		&self,
+		room_id: i64,
+		number: u8,
+		phase: u8,
+	) -> Result<Option<PlannedRound>> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.planned_rounds
+			.iter()
+			.find(|row| {
+				row.room_id == room_id
+					&& row.planned_round.number == number
+					&& row.planned_round.phase == phase
+			})
+			.map(|row| row.planned_round.clone()))
+	}
+
+	fn update_or_create_choice(
+		&self,
+		round_id: i64,
+		player_id: i64,
+		option: ChoiceOption,
+	) -> Result<Choice> {
+		let mut tables = self.tables()?;
+		tables.choice_events.push(ChoiceEventRow {
+			round_id,
+			player_id,
+			event: ChoiceEvent {
+				option,
+				submitted_at: Utc::now().timestamp_millis(),
+			},
+		});
+
+		if let Some(row) = tables
+			.choices
+			.iter_mut()
+			.find(|row| row.round_id == round_id && row.player_id == player_id)
+		{
+			row.choice.option = option;
+			return Ok(row.choice.clone());
+		}
+
+		tables.last_choice_id += 1;
+		let choice = Choice {
+			id: tables.last_choice_id,
+			option,
+			lie: false,
+		};
+		tables.choices.push(ChoiceRow {
+			round_id,
+			player_id,
+			choice: choice.clone(),
+		});
+
+		Ok(choice)
+	}
+
+	fn get_choice_events(&self, round_id: i64, player_id: i64) -> Result<Vec<ChoiceEvent>> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.choice_events
+			.iter()
+			.filter(|row| row.round_id == round_id && row.player_id == player_id)
+			.map(|row| row.event.clone())
+			.collect())
+	}
+
+	fn mark_choice(&self, choice_id: i64, lie: Option<bool>) -> Result<()> {
+		let Some(lie) = lie else {
+			return Ok(());
+		};
+
+		let mut tables = self.tables()?;
+		let row = tables
+			.choices
+			.iter_mut()
+			.find(|row| row.choice.id == choice_id)
+			.ok_or(anyhow!("Could not mark choice"))?;
+		row.choice.lie = lie;
+
+		Ok(())
+	}
+
+	fn get_choices_by_round_id(&self, round_id: i64) -> Result<ChoicesMap> {
+		let tables = self.tables()?;
+
+		Ok(tables
+			.choices
+			.iter()
+			.filter(|row| row.round_id == round_id)
+			.map(|row| (row.player_id, row.choice.clone()))
+			.collect())
+	}
+
+	fn check_player_is_allowed_to_vote(&self, player_id: i64) -> Result<bool> {
+		let tables = self.tables()?;
+
+		tables
+			.players
+			.iter()
+			.find(|row| row.id == player_id)
+			.map(|row| row.can_vote)
+			.ok_or(anyhow!("Could not find player"))
+	}
+}
@@ -0,0 +1,639 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::gamemaster::types::DEFAULT_ROOM_ID;
+
+// One step of the schema, applied once and in order
+pub struct Migration {
+	pub version: u32,
+	pub description: &'static str,
+	apply: fn(&Connection) -> Result<()>,
+}
+
+// Append new steps at the end, never change one that was released.
+// The first ones also bring databases from before migrations up to date,
+// so they check what is already there.
+pub const MIGRATIONS: &[Migration] = &[
+	Migration {
+		version: 1,
+		description: "Create the rooms, players, organizers, rounds, choices and game plan tables",
+		apply: create_base_tables,
+	},
+	Migration {
+		version: 2,
+		description: "Move round options into their own tables",
+		apply: split_round_options,
+	},
+	Migration {
+		version: 3,
+		description: "Scope players, rounds and game plans by room",
+		apply: scope_by_room,
+	},
+	Migration {
+		version: 4,
+		description: "Allow one choice per player and round",
+		apply: unique_choices,
+	},
+	Migration {
+		version: 5,
+		description: "Scope rounds and scores by game",
+		apply: scope_by_game,
+	},
+	Migration {
+		version: 6,
+		description: "Record every vote submission",
+		apply: create_choice_events,
+	},
+	Migration {
+		version: 7,
+		description: "Remember which rounds awarded their points",
+		apply: add_round_resolved,
+	},
+];
+
+pub fn latest_version() -> u32 {
+	MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+pub fn current_version(connection: &Connection) -> Result<u32> {
+	if !has_table(connection, "schema_version")? {
+		return Ok(0);
+	}
+
+	let version: Option<u32> =
+		connection.query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+			row.get(0)
+		})?;
+
+	Ok(version.unwrap_or(0))
+}
+
+pub fn pending_migrations(connection: &Connection) -> Result<Vec<&'static Migration>> {
+	let version = current_version(connection)?;
+	if version > latest_version() {
+		bail!(
+			"The database is at schema version {}, but this server only knows up to {}",
+			version,
+			latest_version()
+		);
+	}
+
+	Ok(MIGRATIONS
+		.iter()
+		.filter(|migration| migration.version > version)
+		.collect())
+}
+
+// Each step commits on its own, a failed step leaves the database at the one before
+pub fn run_migrations(connection: &Connection) -> Result<()> {
+	connection.execute(
+		"CREATE TABLE IF NOT EXISTS 'schema_version' (
+			'version'     INTEGER NOT NULL,
+			'description' VARCHAR(255) NOT NULL,
+			'applied_at'  DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+			PRIMARY KEY('version')
+		)",
+		[],
+	)?;
+
+	for migration in pending_migrations(connection)? {
+		info!(
+			"Migrating the database to version {}: {}",
+			migration.version, migration.description
+		);
+
+		let transaction = connection.unchecked_transaction()?;
+		(migration.apply)(&transaction)
+			.with_context(|| format!("Migration {} failed", migration.version))?;
+		transaction.execute(
+			"INSERT INTO schema_version (version, description) VALUES (?1, ?2)",
+			params![migration.version, migration.description],
+		)?;
+		transaction.commit()?;
+	}
+
+	Ok(())
+}
+
+fn has_table(connection: &Connection, table: &str) -> Result<bool> {
+	let mut statement =
+		connection.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1")?;
+	let check = statement
+		.query_row(params![table], |_row| Ok(()))
+		.optional()?;
+
+	Ok(check.is_some())
+}
+
+fn has_column(connection: &Connection, table: &str, column: &str) -> Result<bool> {
+	let mut statement =
+		connection.prepare(format!("SELECT name FROM pragma_table_info('{}')", table).as_str())?;
+
+	let columns = statement
+		.query_map([], |row| row.get(0))?
+		.collect::<rusqlite::Result<Vec<String>>>()?;
+
+	Ok(columns.iter().any(|name| name == column))
+}
+
+fn create_base_tables(connection: &Connection) -> Result<()> {
+	if !has_table(connection, "Rooms")? {
+		connection.execute(
+			"CREATE TABLE 'Rooms' (
+				'id'   INTEGER,
+				'code' VARCHAR(16) NOT NULL,
+				'name' VARCHAR(255) NOT NULL,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			)",
+			[],
+		)?;
+		connection.execute("CREATE UNIQUE INDEX 'code_index' ON 'Rooms' ('code')", [])?;
+		connection.execute(
+			"INSERT INTO Rooms (id, code, name) VALUES (?1, 'MAIN', 'Main room')",
+			params![DEFAULT_ROOM_ID],
+		)?;
+	}
+
+	if !has_table(connection, "Players")? {
+		connection.execute(
+			"CREATE TABLE 'Players' (
+				'id'          INTEGER,
+				'room_id'     INTEGER NOT NULL DEFAULT 1,
+				'name'        VARCHAR(255) NOT NULL,
+				'points'      INTEGER DEFAULT 0,
+				'can_vote'    BOOLEAN DEFAULT FALSE,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE UNIQUE INDEX 'room_name_index' ON 'Players' ('room_id', 'name')",
+			[],
+		)?;
+	}
+
+	if !has_table(connection, "Organizers")? {
+		connection.execute(
+			"CREATE TABLE 'Organizers' (
+				'id'            INTEGER,
+				'username'      VARCHAR(255) NOT NULL,
+				'password_hash' VARCHAR(255) NOT NULL,
+				'name'          VARCHAR(255) NOT NULL,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE UNIQUE INDEX 'username_index' ON 'Organizers' ('username')",
+			[],
+		)?;
+	}
+
+	if !has_table(connection, "Rounds")? {
+		connection.execute(
+			"CREATE TABLE 'Rounds' (
+				'id'       INTEGER,
+				'room_id'  INTEGER NOT NULL DEFAULT 1,
+				'number'   INTEGER NOT NULL,
+				'phase'    INTEGER NOT NULL,
+				'state'    INTEGER DEFAULT 0,
+				'question' VARCHAR(255) NOT NULL,
+				'choice_a' VARCHAR(255) NOT NULL,
+				'choice_b' VARCHAR(255) NOT NULL,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE UNIQUE INDEX 'room_number_phase_index'
+				ON 'Rounds' ('room_id', 'number', 'phase')",
+			[],
+		)?;
+	}
+
+	if !has_table(connection, "Choices")? {
+		connection.execute(
+			"CREATE TABLE 'Choices' (
+				'id'        INTEGER NOT NULL,
+				'round_id'  INTEGER NOT NULL,
+				'player_id' INTEGER NOT NULL,
+				'option'    TEXT(1) NOT NULL,
+				'lie'       INTEGER(1) DEFAULT 0 NOT NULL,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE INDEX 'round_id_index' ON 'Choices' ('round_id')",
+			[],
+		)?;
+		connection.execute(
+			"CREATE INDEX 'player_id_index' ON 'Choices' ('player_id')",
+			[],
+		)?;
+	}
+
+	if !has_table(connection, "PlannedRounds")? {
+		connection.execute(
+			"CREATE TABLE 'PlannedRounds' (
+				'id'             INTEGER,
+				'room_id'        INTEGER NOT NULL DEFAULT 1,
+				'number'         INTEGER NOT NULL,
+				'phase'          INTEGER NOT NULL,
+				'question'       VARCHAR(255) NOT NULL,
+				'choice_a'       VARCHAR(255) NOT NULL,
+				'choice_b'       VARCHAR(255) NOT NULL,
+				'voting_seconds' INTEGER,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE UNIQUE INDEX 'planned_room_number_phase_index'
+				ON 'PlannedRounds' ('room_id', 'number', 'phase')",
+			[],
+		)?;
+	}
+
+	Ok(())
+}
+
+fn split_round_options(connection: &Connection) -> Result<()> {
+	if !has_table(connection, "RoundOptions")? {
+		connection.execute(
+			"CREATE TABLE 'RoundOptions' (
+				'id'       INTEGER,
+				'round_id' INTEGER NOT NULL,
+				'position' INTEGER NOT NULL,
+				'text'     VARCHAR(255) NOT NULL,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE UNIQUE INDEX 'round_position_index' ON 'RoundOptions' ('round_id', 'position')",
+			[],
+		)?;
+
+		// Rounds from before options had their own table
+		connection.execute(
+			"INSERT INTO RoundOptions (round_id, position, text)
+				SELECT id, 0, choice_a FROM Rounds
+				UNION ALL SELECT id, 1, choice_b FROM Rounds",
+			[],
+		)?;
+	}
+
+	if !has_table(connection, "PlannedRoundOptions")? {
+		connection.execute(
+			"CREATE TABLE 'PlannedRoundOptions' (
+				'id'               INTEGER,
+				'planned_round_id' INTEGER NOT NULL,
+				'position'         INTEGER NOT NULL,
+				'text'             VARCHAR(255) NOT NULL,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE UNIQUE INDEX 'planned_round_position_index'
+				ON 'PlannedRoundOptions' ('planned_round_id', 'position')",
+			[],
+		)?;
+
+		connection.execute(
+			"INSERT INTO PlannedRoundOptions (planned_round_id, position, text)
+				SELECT id, 0, choice_a FROM PlannedRounds
+				UNION ALL SELECT id, 1, choice_b FROM PlannedRounds",
+			[],
+		)?;
+	}
+
+	Ok(())
+}
+
+// Databases from before rooms keep everything in the default room
+fn scope_by_room(connection: &Connection) -> Result<()> {
+	let room_scoped_tables = [
+		(
+			"Players",
+			"name_index",
+			"room_name_index",
+			"'room_id', 'name'",
+		),
+		(
+			"Rounds",
+			"number_phase_index",
+			"room_number_phase_index",
+			"'room_id', 'number', 'phase'",
+		),
+		(
+			"PlannedRounds",
+			"planned_number_phase_index",
+			"planned_room_number_phase_index",
+			"'room_id', 'number', 'phase'",
+		),
+	];
+	for (table, old_index, new_index, columns) in room_scoped_tables {
+		if has_column(connection, table, "room_id")? {
+			continue;
+		}
+
+		connection.execute(
+			format!(
+				"ALTER TABLE '{}' ADD COLUMN 'room_id' INTEGER NOT NULL DEFAULT {}",
+				table, DEFAULT_ROOM_ID
+			)
+			.as_str(),
+			[],
+		)?;
+		connection.execute(format!("DROP INDEX IF EXISTS '{}'", old_index).as_str(), [])?;
+		connection.execute(
+			format!(
+				"CREATE UNIQUE INDEX '{}' ON '{}' ({})",
+				new_index, table, columns
+			)
+			.as_str(),
+			[],
+		)?;
+	}
+
+	Ok(())
+}
+
+// Lets a vote be stored with a single upsert
+fn unique_choices(connection: &Connection) -> Result<()> {
+	// The game only ever read and updated the first choice of a player
+	connection.execute(
+		"DELETE FROM Choices WHERE id NOT IN
+			(SELECT MIN(id) FROM Choices GROUP BY round_id, player_id)",
+		[],
+	)?;
+	connection.execute(
+		"CREATE UNIQUE INDEX 'round_player_index' ON 'Choices' ('round_id', 'player_id')",
+		[],
+	)?;
+
+	Ok(())
+}
+
+// Everything played so far becomes the first game of its room
+fn scope_by_game(connection: &Connection) -> Result<()> {
+	connection.execute(
+		"CREATE TABLE 'Games' (
+			'id'          INTEGER,
+			'room_id'     INTEGER NOT NULL,
+			'name'        VARCHAR(255) NOT NULL,
+			'started_at'  DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+			'archived_at' DATETIME,
+			PRIMARY KEY('id' AUTOINCREMENT)
+		)",
+		[],
+	)?;
+	connection.execute("CREATE INDEX 'game_room_index' ON 'Games' ('room_id')", [])?;
+	connection.execute(
+		"INSERT INTO Games (room_id, name) SELECT id, 'Game 1' FROM Rooms ORDER BY id",
+		[],
+	)?;
+
+	connection.execute(
+		"ALTER TABLE 'Rooms' ADD COLUMN 'current_game_id' INTEGER",
+		[],
+	)?;
+	connection.execute(
+		"UPDATE Rooms SET current_game_id = (SELECT id FROM Games WHERE room_id = Rooms.id)",
+		[],
+	)?;
+
+	connection.execute(
+		"ALTER TABLE 'Rounds' ADD COLUMN 'game_id' INTEGER NOT NULL DEFAULT 0",
+		[],
+	)?;
+	connection.execute(
+		"UPDATE Rounds SET game_id = COALESCE(
+			(SELECT current_game_id FROM Rooms WHERE id = Rounds.room_id), 0)",
+		[],
+	)?;
+	connection.execute("DROP INDEX IF EXISTS 'room_number_phase_index'", [])?;
+	connection.execute(
+		"CREATE UNIQUE INDEX 'game_number_phase_index' ON 'Rounds' ('game_id', 'number', 'phase')",
+		[],
+	)?;
+
+	// Players.points stays for older readers of the database, but is no longer updated
+	connection.execute(
+		"CREATE TABLE 'Scores' (
+			'game_id'   INTEGER NOT NULL,
+			'player_id' INTEGER NOT NULL,
+			'points'    INTEGER NOT NULL DEFAULT 0,
+			PRIMARY KEY('game_id', 'player_id')
+		)",
+		[],
+	)?;
+	connection.execute(
+		"INSERT INTO Scores (game_id, player_id, points)
+			SELECT Rooms.current_game_id, Players.id, Players.points
+			FROM Players JOIN Rooms ON Rooms.id = Players.room_id
+			WHERE Players.points > 0",
+		[],
+	)?;
+
+	Ok(())
+}
+
+// Votes cast before this have no history, only their current choice
+fn create_choice_events(connection: &Connection) -> Result<()> {
+	connection.execute(
+		"CREATE TABLE 'ChoiceEvents' (
+			'id'           INTEGER,
+			'round_id'     INTEGER NOT NULL,
+			'player_id'    INTEGER NOT NULL,
+			'option'       TEXT(1) NOT NULL,
+			'submitted_at' DATETIME NOT NULL,
+			PRIMARY KEY('id' AUTOINCREMENT)
+		)",
+		[],
+	)?;
+	connection.execute(
+		"CREATE INDEX 'event_round_player_index' ON 'ChoiceEvents' ('round_id', 'player_id')",
+		[],
+	)?;
+
+	Ok(())
+}
+
+// Rounds showing their results (state 7) have already been scored
+fn add_round_resolved(connection: &Connection) -> Result<()> {
+	connection.execute(
+		"ALTER TABLE 'Rounds' ADD COLUMN 'resolved' BOOLEAN NOT NULL DEFAULT FALSE",
+		[],
+	)?;
+	connection.execute("UPDATE Rounds SET resolved = TRUE WHERE state = 7", [])?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// The tables as the server created them before there were migrations
+	fn create_baseline_tables(connection: &Connection) -> Result<()> {
+		connection.execute_batch(
+			"CREATE TABLE 'Players' (
+				'id'          INTEGER,
+				'name'        VARCHAR(255) NOT NULL,
+				'points'      INTEGER DEFAULT 0,
+				'can_vote'    BOOLEAN DEFAULT FALSE,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			);
+			CREATE UNIQUE INDEX 'name_index' ON 'Players' ('name');
+			CREATE TABLE 'Rounds' (
+				'id'       INTEGER,
+				'number'   INTEGER NOT NULL,
+				'phase'    INTEGER NOT NULL,
+				'state'    INTEGER DEFAULT 0,
+				'question' VARCHAR(255) NOT NULL,
+				'choice_a' VARCHAR(255) NOT NULL,
+				'choice_b' VARCHAR(255) NOT NULL,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			);
+			CREATE UNIQUE INDEX 'number_phase_index' ON 'Rounds' ('number', 'phase');
+			CREATE TABLE 'Choices' (
+				'id'        INTEGER NOT NULL,
+				'round_id'  INTEGER NOT NULL,
+				'player_id' INTEGER NOT NULL,
+				'option'    TEXT(1) NOT NULL,
+				'lie'       INTEGER(1) DEFAULT 0 NOT NULL,
+				PRIMARY KEY('id' AUTOINCREMENT)
+			);
+			CREATE INDEX 'round_id_index' ON 'Choices' ('round_id');
+			CREATE INDEX 'player_id_index' ON 'Choices' ('player_id');
+
+			INSERT INTO Players (name, points, can_vote) VALUES ('alpha', 3, TRUE), ('bravo', 0, FALSE);
+			INSERT INTO Rounds (number, phase, state, question, choice_a, choice_b)
+				VALUES (1, 1, 7, 'Cats or dogs?', 'Cats', 'Dogs'),
+					(1, 2, 3, 'Tea or coffee?', 'Tea', 'Coffee');
+			INSERT INTO Choices (round_id, player_id, option, lie)
+				VALUES (1, 1, 'a', 0), (1, 2, 'b', 1), (2, 1, 'b', 0), (2, 1, 'a', 0);",
+		)?;
+
+		Ok(())
+	}
+
+	#[test]
+	fn migrates_a_database_from_before_migrations() -> Result<()> {
+		let connection = Connection::open_in_memory()?;
+		create_baseline_tables(&connection)?;
+		assert_eq!(current_version(&connection)?, 0);
+		assert_eq!(pending_migrations(&connection)?.len(), MIGRATIONS.len());
+
+		run_migrations(&connection)?;
+		assert_eq!(current_version(&connection)?, latest_version());
+		assert!(pending_migrations(&connection)?.is_empty());
+
+		// Everything lands in the first game of the main room
+		let rooms: Vec<(i64, String, i64)> = connection
+			.prepare("SELECT id, code, current_game_id FROM Rooms")?
+			.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+			.collect::<rusqlite::Result<_>>()?;
+		assert_eq!(rooms, vec![(DEFAULT_ROOM_ID, "MAIN".to_owned(), 1)]);
+
+		let players: Vec<(String, i64)> = connection
+			.prepare("SELECT name, room_id FROM Players ORDER BY id")?
+			.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+			.collect::<rusqlite::Result<_>>()?;
+		assert_eq!(
+			players,
+			vec![
+				("alpha".to_owned(), DEFAULT_ROOM_ID),
+				("bravo".to_owned(), DEFAULT_ROOM_ID)
+			]
+		);
+
+		let scores: Vec<(i64, i64, i64)> = connection
+			.prepare("SELECT game_id, player_id, points FROM Scores")?
+			.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+			.collect::<rusqlite::Result<_>>()?;
+		assert_eq!(scores, vec![(1, 1, 3)]);
+
+		// Only the round that was showing its results counts as scored
+		let rounds: Vec<(i64, i64, i64, bool)> = connection
+			.prepare("SELECT id, room_id, game_id, resolved FROM Rounds ORDER BY id")?
+			.query_map([], |row| {
+				Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+			})?
+			.collect::<rusqlite::Result<_>>()?;
+		assert_eq!(
+			rounds,
+			vec![
+				(1, DEFAULT_ROOM_ID, 1, true),
+				(2, DEFAULT_ROOM_ID, 1, false)
+			]
+		);
+
+		let options: Vec<(i64, i64, String)> = connection
+			.prepare(
+				"SELECT round_id, position, text FROM RoundOptions ORDER BY round_id, position",
+			)?
+			.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+			.collect::<rusqlite::Result<_>>()?;
+		assert_eq!(
+			options,
+			vec![
+				(1, 0, "Cats".to_owned()),
+				(1, 1, "Dogs".to_owned()),
+				(2, 0, "Tea".to_owned()),
+				(2, 1, "Coffee".to_owned())
+			]
+		);
+
+		// The first choice of a player was the one the game used
+		let choices: Vec<(i64, i64, i64, String)> = connection
+			.prepare("SELECT id, round_id, player_id, option FROM Choices ORDER BY id")?
+			.query_map([], |row| {
+				Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+			})?
+			.collect::<rusqlite::Result<_>>()?;
+		assert_eq!(
+			choices,
+			vec![
+				(1, 1, 1, "a".to_owned()),
+				(2, 1, 2, "b".to_owned()),
+				(3, 2, 1, "b".to_owned())
+			]
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn migrating_twice_changes_nothing() -> Result<()> {
+		let connection = Connection::open_in_memory()?;
+		create_baseline_tables(&connection)?;
+
+		run_migrations(&connection)?;
+		run_migrations(&connection)?;
+
+		let applied: i64 =
+			connection.query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))?;
+		assert_eq!(applied, i64::from(latest_version()));
+
+		Ok(())
+	}
+
+	#[test]
+	fn refuses_a_database_from_a_newer_server() -> Result<()> {
+		let connection = Connection::open_in_memory()?;
+		run_migrations(&connection)?;
+		connection.execute(
+			"INSERT INTO schema_version (version, description) VALUES (?1, 'From the future')",
+			params![latest_version() + 1],
+		)?;
+
+		assert!(pending_migrations(&connection).is_err());
+
+		Ok(())
+	}
+}
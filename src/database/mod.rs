@@ -1,2 +1,5 @@
+#[allow(clippy::module_inception)]
 pub mod database;
+pub mod memory;
+pub mod migrations;
 pub mod sqlite;
@@ -1,117 +1,257 @@
 use std::{collections::HashMap, fmt::Error};
 
 use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
 use rusqlite::{
 	params,
 	types::{FromSql, FromSqlError},
 	Connection, OptionalExtension, ToSql,
 };
 
-use crate::gamemaster::types::{Choice, ChoiceOption, ChoicesMap, Player, Round, RoundState};
+use crate::gamemaster::types::{
+	Choice, ChoiceEvent, ChoiceOption, ChoicesMap, Game, Organizer, OrganizerAccount, PlannedRound,
+	Player, Room, Round, RoundState,
+};
 
-use super::database::Database;
+use super::{
+	database::{transaction, Database, FIRST_GAME_NAME},
+	migrations::{pending_migrations, run_migrations, Migration},
+};
 
 pub struct SqliteDatabase {
-	connection: Connection,
+	// Taken out when the database is closed
+	connection: Option<Connection>,
 }
 
 impl SqliteDatabase {
 	pub fn new(path: &str) -> Self {
-		let connection = Connection::open(path).unwrap();
-
-		let new = Self {
-			connection: connection,
-		};
+		let new = Self::open(path).unwrap();
 		new.initialize_database().unwrap();
 
 		new
 	}
+
+	// Opens the database without migrating it
+	pub fn open(path: &str) -> Result<Self> {
+		let connection = Connection::open(path)?;
+
+		Ok(Self {
+			connection: Some(connection),
+		})
+	}
+
+	// Every organizer account, for seeding another database
+	pub fn get_organizer_accounts(&self) -> Result<Vec<OrganizerAccount>> {
+		let mut statement = self
+			.connection()?
+			.prepare("SELECT id, username, name, password_hash FROM Organizers ORDER BY id")?;
+
+		let accounts = statement
+			.query_map([], |row| {
+				Ok(OrganizerAccount {
+					organizer: Organizer {
+						id: row.get(0)?,
+						username: row.get(1)?,
+						name: row.get(2)?,
+					},
+					password_hash: row.get(3)?,
+				})
+			})?
+			.collect::<rusqlite::Result<Vec<OrganizerAccount>>>()?;
+
+		Ok(accounts)
+	}
+
+	pub fn pending_migrations(&self) -> Result<Vec<&'static Migration>> {
+		pending_migrations(self.connection()?)
+	}
+
+	fn connection(&self) -> Result<&Connection> {
+		match &self.connection {
+			Some(connection) => Ok(connection),
+			None => bail!("The database is closed"),
+		}
+	}
 }
 
 impl Database for SqliteDatabase {
+	fn close(&mut self) -> Result<()> {
+		let Some(connection) = self.connection.take() else {
+			return Ok(());
+		};
+
+		if let Err((connection, error)) = connection.close() {
+			self.connection = Some(connection);
+			bail!("Could not close the database: {}", error);
+		}
+
+		Ok(())
+	}
+
 	fn initialize_database(&self) -> Result<()> {
-		let mut statement = self
-			.connection
-			.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'Players'")?;
-		let check = statement.query_row([], |_row| Ok(())).optional()?;
-
-		if check.is_none() {
-			self.connection.execute(
-				"CREATE TABLE 'Players' (
-					'id'          INTEGER,
-					'name'        VARCHAR(255) NOT NULL,
-					'points'      INTEGER DEFAULT 0,
-					'can_vote'    BOOLEAN DEFAULT FALSE,
-					PRIMARY KEY('id' AUTOINCREMENT)
-				)",
-				[],
-			)?;
-			self.connection
-				.execute("CREATE UNIQUE INDEX 'name_index' ON 'Players' ('name')", [])?;
+		run_migrations(self.connection()?)
+	}
+
+	// Savepoints rather than BEGIN, so transactions can nest
+	fn begin_transaction(&self) -> Result<()> {
+		self.connection()?.execute("SAVEPOINT unit_of_work", [])?;
+
+		Ok(())
+	}
+
+	fn commit_transaction(&self) -> Result<()> {
+		self.connection()?.execute("RELEASE unit_of_work", [])?;
+
+		Ok(())
+	}
+
+	fn rollback_transaction(&self) -> Result<()> {
+		self.connection()?
+			.execute_batch("ROLLBACK TO unit_of_work; RELEASE unit_of_work")?;
+
+		Ok(())
+	}
+
+	fn rollback_open_transactions(&self) -> Result<()> {
+		// Releasing the outermost savepoint ends the transaction
+		while !self.connection()?.is_autocommit() {
+			self.rollback_transaction()?;
 		}
 
+		Ok(())
+	}
+
+	fn get_rooms(&self) -> Result<Vec<Room>> {
 		let mut statement = self
-			.connection
-			.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'Rounds'")?;
-		let check = statement.query_row([], |_row| Ok(())).optional()?;
-
-		if check.is_none() {
-			self.connection.execute(
-				"CREATE TABLE 'Rounds' (
-					'id'       INTEGER,
-					'number'   INTEGER NOT NULL,
-					'phase'    INTEGER NOT NULL,
-					'state'    INTEGER DEFAULT 0,
-					'question' VARCHAR(255) NOT NULL,
-					'choice_a' VARCHAR(255) NOT NULL,
-					'choice_b' VARCHAR(255) NOT NULL,
-					PRIMARY KEY('id' AUTOINCREMENT)
-				)",
-				[],
-			)?;
-			self.connection.execute(
-				"CREATE UNIQUE INDEX 'number_phase_index' ON 'Rounds' ('number', 'phase')",
-				[],
-			)?;
-		}
+			.connection()?
+			.prepare("SELECT id, code, name FROM Rooms ORDER BY id")?;
 
+		let rooms = statement
+			.query_map([], |row| {
+				Ok(Room {
+					id: row.get(0)?,
+					code: row.get(1)?,
+					name: row.get(2)?,
+				})
+			})?
+			.collect::<rusqlite::Result<Vec<Room>>>()?;
+
+		Ok(rooms)
+	}
+
+	fn find_room_by_id(&self, id: i64) -> Result<Option<Room>> {
 		let mut statement = self
-			.connection
-			.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'Choices'")?;
-		let check = statement.query_row([], |_row| Ok(())).optional()?;
-
-		if check.is_none() {
-			self.connection.execute(
-				"CREATE TABLE 'Choices' (
-					'id'        INTEGER NOT NULL,
-					'round_id'  INTEGER NOT NULL,
-					'player_id' INTEGER NOT NULL,
-					'option'    TEXT(1) NOT NULL,
-					'lie'       INTEGER(1) DEFAULT 0 NOT NULL,
-					PRIMARY KEY('id' AUTOINCREMENT)
-				)",
-				[],
-			)?;
-			self.connection.execute(
-				"CREATE INDEX 'round_id_index' ON 'Choices' ('round_id')",
-				[],
+			.connection()?
+			.prepare("SELECT id, code, name FROM Rooms WHERE id = ?1")?;
+
+		let find = statement
+			.query_row(params![id], |row| {
+				Ok(Room {
+					id: row.get(0)?,
+					code: row.get(1)?,
+					name: row.get(2)?,
+				})
+			})
+			.optional()?;
+
+		Ok(find)
+	}
+
+	fn find_room_by_code(&self, code: &str) -> Result<Option<Room>> {
+		let mut statement = self
+			.connection()?
+			.prepare("SELECT id, code, name FROM Rooms WHERE code = ?1")?;
+
+		let find = statement
+			.query_row(params![code], |row| {
+				Ok(Room {
+					id: row.get(0)?,
+					code: row.get(1)?,
+					name: row.get(2)?,
+				})
+			})
+			.optional()?;
+
+		Ok(find)
+	}
+
+	fn create_room(&self, code: &str, name: &str) -> Result<Room> {
+		transaction(self, |_| {
+			let mut statement = self
+				.connection()?
+				.prepare("INSERT INTO Rooms (code, name) VALUES (?1, ?2)")?;
+			let affected = statement.execute(params![code, name])?;
+
+			if affected != 1 {
+				bail!("Incorrect number of affected rows")
+			}
+
+			let room = self
+				.find_room_by_code(code)?
+				.ok_or(anyhow!("Could not find created room"))?;
+			self.insert_game(room.id, FIRST_GAME_NAME)?;
+
+			Ok(room)
+		})
+	}
+
+	fn get_current_game(&self, room_id: i64) -> Result<Game> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT id, name, started_at, archived_at FROM Games
+				WHERE id = (SELECT current_game_id FROM Rooms WHERE id = ?1)",
+		)?;
+
+		let find = statement
+			.query_row(params![room_id], game_from_row)
+			.optional()?;
+
+		find.ok_or(anyhow!("Room {} has no current game", room_id))
+	}
+
+	fn get_games(&self, room_id: i64) -> Result<Vec<Game>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT id, name, started_at, archived_at FROM Games WHERE room_id = ?1 ORDER BY id",
+		)?;
+
+		let games = statement
+			.query_map(params![room_id], game_from_row)?
+			.collect::<rusqlite::Result<Vec<Game>>>()?;
+
+		Ok(games)
+	}
+
+	fn start_game(&self, room_id: i64, name: &str) -> Result<Game> {
+		transaction(self, |_| {
+			if self.find_room_by_id(room_id)?.is_none() {
+				bail!("Could not find room {}", room_id);
+			}
+
+			self.connection()?.execute(
+				"UPDATE Games SET archived_at = ?1
+					WHERE id = (SELECT current_game_id FROM Rooms WHERE id = ?2)",
+				params![Utc::now(), room_id],
 			)?;
-			self.connection.execute(
-				"CREATE INDEX 'player_id_index' ON 'Choices' ('player_id')",
-				[],
+			// Players are let back in by the first round of the new game
+			self.connection()?.execute(
+				"UPDATE Players SET can_vote = FALSE WHERE room_id = ?1",
+				params![room_id],
 			)?;
-		}
 
-		Ok(())
+			self.insert_game(room_id, name)
+		})
 	}
 
-	fn find_player_by_id(&self, id: u8) -> Result<Option<Player>> {
-		let mut statement = self
-			.connection
-			.prepare("SELECT id, name, points, can_vote FROM Players WHERE id = ?1")?;
+	fn find_player_by_id(&self, room_id: i64, id: i64) -> Result<Option<Player>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT Players.id, Players.name, COALESCE(Scores.points, 0), can_vote FROM Players
+				JOIN Rooms ON Rooms.id = Players.room_id
+				LEFT JOIN Scores ON Scores.game_id = Rooms.current_game_id
+					AND Scores.player_id = Players.id
+				WHERE Players.id = ?1 AND Players.room_id = ?2",
+		)?;
 
 		let find = statement
-			.query_row(params![id], |row| {
+			.query_row(params![id, room_id], |row| {
 				Ok(Player {
 					id: row.get(0)?,
 					name: row.get(1)?,
@@ -124,13 +264,17 @@ impl Database for SqliteDatabase {
 		Ok(find)
 	}
 
-	fn find_player_by_name(&self, name: &str) -> Result<Option<Player>> {
-		let mut statement = self
-			.connection
-			.prepare("SELECT id, name, points, can_vote FROM Players WHERE name = ?1")?;
+	fn find_player_by_name(&self, room_id: i64, name: &str) -> Result<Option<Player>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT Players.id, Players.name, COALESCE(Scores.points, 0), can_vote FROM Players
+				JOIN Rooms ON Rooms.id = Players.room_id
+				LEFT JOIN Scores ON Scores.game_id = Rooms.current_game_id
+					AND Scores.player_id = Players.id
+				WHERE Players.name = ?1 AND Players.room_id = ?2",
+		)?;
 
 		let find = statement
-			.query_row(params![name], |row| {
+			.query_row(params![name, room_id], |row| {
 				Ok(Player {
 					id: row.get(0)?,
 					name: row.get(1)?,
@@ -143,70 +287,115 @@ impl Database for SqliteDatabase {
 		Ok(find)
 	}
 
-	fn create_player(&self, name: &str) -> Result<Player> {
+	fn create_player(&self, room_id: i64, name: &str) -> Result<Player> {
 		let mut statement = self
-			.connection
-			.prepare("INSERT INTO Players (name) VALUES (?1)")?;
-		let affected = statement.execute(params![name])?;
+			.connection()?
+			.prepare("INSERT INTO Players (room_id, name) VALUES (?1, ?2)")?;
+		let affected = statement.execute(params![room_id, name])?;
 
 		if affected != 1 {
 			bail!("Incorrect number of affected rows")
 		}
 
-		self.find_player_by_name(name)?
+		self.find_player_by_name(room_id, name)?
 			.ok_or(anyhow!("Could not find crated player"))
 	}
 
-	fn find_or_create_player(&self, name: &str) -> Result<Player> {
-		match self.find_player_by_name(name)? {
+	fn find_or_create_player(&self, room_id: i64, name: &str) -> Result<Player> {
+		// An insert that runs into the existing name would still use up an id
+		match self.find_player_by_name(room_id, name)? {
 			Some(player) => Ok(player),
-			None => self.create_player(name),
+			None => self.create_player(room_id, name),
 		}
 	}
 
-	fn mark_player(&self, id: u8, points: Option<usize>, can_vote: Option<bool>) -> Result<Player> {
-		let mut columns: Vec<&str> = vec![];
-
-		if points.is_some() {
-			columns.push("points = :points");
-		}
-		if can_vote.is_some() {
-			columns.push("can_vote = :canvote");
+	fn mark_player(
+		&self,
+		room_id: i64,
+		id: i64,
+		points: Option<usize>,
+		can_vote: Option<bool>,
+	) -> Result<Player> {
+		if points.is_none() && can_vote.is_none() {
+			bail!("Could not update the player");
 		}
 
-		let columns = columns.join(", ");
+		// Points count towards the current game only
+		transaction(self, |_| {
+			if self.find_player_by_id(room_id, id)?.is_none() {
+				bail!("Could not update the player");
+			}
 
-		let mut statement = self
-			.connection
-			.prepare(format!("UPDATE Players SET {} WHERE id = :id", columns).as_str())?;
+			if let Some(points) = points {
+				self.connection()?.execute(
+					"INSERT INTO Scores (game_id, player_id, points)
+						VALUES ((SELECT current_game_id FROM Rooms WHERE id = ?1), ?2, ?3)
+						ON CONFLICT (game_id, player_id) DO UPDATE SET points = excluded.points",
+					params![room_id, id, points],
+				)?;
+			}
+			if let Some(can_vote) = can_vote {
+				self.connection()?.execute(
+					"UPDATE Players SET can_vote = ?1 WHERE id = ?2 AND room_id = ?3",
+					params![can_vote, id, room_id],
+				)?;
+			}
 
-		let get_index = statement.parameter_index(":points")?;
-		if let Some(points_index) = get_index {
-			statement.raw_bind_parameter(points_index, points.unwrap())?;
-		}
-		let get_index = statement.parameter_index(":canvote")?;
-		if let Some(can_vote_index) = get_index {
-			statement.raw_bind_parameter(can_vote_index, can_vote.unwrap())?;
-		}
+			self.find_player_by_id(room_id, id)?
+				.ok_or(anyhow!("Could not find updated player"))
+		})
+	}
 
-		let get_index = statement.parameter_index(":id")?;
-		statement.raw_bind_parameter(get_index.unwrap(), id)?;
-		let update = statement.raw_execute()?;
+	fn find_organizer_by_username(&self, username: &str) -> Result<Option<OrganizerAccount>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT id, username, name, password_hash FROM Organizers WHERE username = ?1",
+		)?;
 
-		if update != 1 {
-			bail!("Could not update the player");
+		let find = statement
+			.query_row(params![username], |row| {
+				Ok(OrganizerAccount {
+					organizer: Organizer {
+						id: row.get(0)?,
+						username: row.get(1)?,
+						name: row.get(2)?,
+					},
+					password_hash: row.get(3)?,
+				})
+			})
+			.optional()?;
+
+		Ok(find)
+	}
+
+	fn create_organizer(
+		&self,
+		username: &str,
+		password_hash: &str,
+		name: &str,
+	) -> Result<Organizer> {
+		let mut statement = self.connection()?.prepare(
+			"INSERT INTO Organizers (username, password_hash, name) VALUES (?1, ?2, ?3)",
+		)?;
+		let affected = statement.execute(params![username, password_hash, name])?;
+
+		if affected != 1 {
+			bail!("Incorrect number of affected rows")
 		}
-		Ok(self.find_player_by_id(id)?.unwrap())
+
+		self.find_organizer_by_username(username)?
+			.map(|account| account.organizer)
+			.ok_or(anyhow!("Could not find created organizer"))
 	}
 
-	fn get_active_round(&self) -> Result<Option<Round>> {
-		let mut statement = self
-			.connection
-			.prepare("SELECT id FROM Rounds ORDER BY number DESC, phase DESC LIMIT 1")?;
+	fn get_active_round(&self, room_id: i64) -> Result<Option<Round>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT id FROM Rounds WHERE game_id = (SELECT current_game_id FROM Rooms WHERE id = ?1)
+				ORDER BY number DESC, phase DESC LIMIT 1",
+		)?;
 
 		let find = statement
-			.query_row(params![], |row| {
-				let id: u8 = row.get(0)?;
+			.query_row(params![room_id], |row| {
+				let id: i64 = row.get(0)?;
 				Ok(id)
 			})
 			.optional()?;
@@ -219,34 +408,47 @@ impl Database for SqliteDatabase {
 
 	fn create_round(
 		&self,
+		room_id: i64,
 		number: u8,
 		phase: u8,
 		state: RoundState,
 		question: String,
-		choice_a: String,
-		choice_b: String,
+		options: Vec<String>,
 	) -> Result<Round> {
-		let mut statement = self.connection.prepare(
-			"INSERT INTO Rounds (number, phase, state, question, choice_a, choice_b)
-				VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-		)?;
-		let affected =
-			statement.execute(params![number, phase, state, question, choice_a, choice_b])?;
-
-		if affected != 1 {
-			bail!("Incorrect number of affected rows")
-		}
+		// choice_a and choice_b only stay filled in for older readers of the database
+		transaction(self, |_| {
+			let mut statement = self.connection()?.prepare(
+				"INSERT INTO Rounds
+					(room_id, game_id, number, phase, state, question, choice_a, choice_b)
+					VALUES (?1, (SELECT current_game_id FROM Rooms WHERE id = ?1),
+						?2, ?3, ?4, ?5, ?6, ?7)",
+			)?;
+			let affected = statement.execute(params![
+				room_id,
+				number,
+				phase,
+				state,
+				question,
+				options.first().cloned().unwrap_or_default(),
+				options.get(1).cloned().unwrap_or_default()
+			])?;
+
+			if affected != 1 {
+				bail!("Incorrect number of affected rows")
+			}
 
-		let last_inserted_id = self.connection.last_insert_rowid();
+			let last_inserted_id = self.connection()?.last_insert_rowid();
+			self.set_round_options(last_inserted_id, &options)?;
 
-		self.get_round_by_id(u8::try_from(last_inserted_id)?)?
-			.ok_or(anyhow!("Could not find created round"))
+			self.get_round_by_id(last_inserted_id)?
+				.ok_or(anyhow!("Could not find created round"))
+		})
 	}
 
-	fn get_choices_by_round_id(&self, round_id: u8) -> Result<ChoicesMap> {
+	fn get_choices_by_round_id(&self, round_id: i64) -> Result<ChoicesMap> {
 		let mut choices = HashMap::new();
 
-		let mut statement = self.connection.prepare(
+		let mut statement = self.connection()?.prepare(
 			"SELECT player_id, id, option, lie
 				FROM Choices WHERE round_id = ?1",
 		)?;
@@ -266,80 +468,222 @@ impl Database for SqliteDatabase {
 		Ok(choices)
 	}
 
-	fn find_round_by_number_and_phase(&self, number: u8, phase: u8) -> Result<Option<Round>> {
-		let mut statement = self.connection.prepare(
-			"SELECT id, number, phase, state, question, choice_a, choice_b
-			FROM Rounds WHERE number = ?1 AND phase = ?2",
+	fn find_round_by_number_and_phase(
+		&self,
+		room_id: i64,
+		number: u8,
+		phase: u8,
+	) -> Result<Option<Round>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT id FROM Rounds WHERE number = ?2 AND phase = ?3
+				AND game_id = (SELECT current_game_id FROM Rooms WHERE id = ?1)",
 		)?;
 
 		let find = statement
-			.query_row(params![number, phase], |row| {
-				Ok(Round {
-					id: row.get(0)?,
-					number: row.get(1)?,
-					phase: row.get(2)?,
-					state: row.get(3)?,
-					question: row.get(4)?,
-					choice_a: row.get(5)?,
-					choice_b: row.get(6)?,
-				})
+			.query_row(params![room_id, number, phase], |row| {
+				let id: i64 = row.get(0)?;
+				Ok(id)
 			})
 			.optional()?;
 
-		Ok(find)
+		match find {
+			Some(round_id) => self.get_round_by_id(round_id),
+			None => Ok(None),
+		}
+	}
+
+	fn find_round_in_room(&self, room_id: i64, round_id: i64) -> Result<Option<Round>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT Rounds.id FROM Rounds JOIN Games ON Games.id = Rounds.game_id
+				WHERE Rounds.id = ?2 AND Games.room_id = ?1",
+		)?;
+
+		let find = statement
+			.query_row(params![room_id, round_id], |row| {
+				let id: i64 = row.get(0)?;
+				Ok(id)
+			})
+			.optional()?;
+
+		match find {
+			Some(round_id) => self.get_round_by_id(round_id),
+			None => Ok(None),
+		}
 	}
 
 	fn update_round(
 		&self,
+		room_id: i64,
 		number: u8,
 		phase: u8,
 		state: Option<RoundState>,
 		question: Option<String>,
-		choice_a: Option<String>,
-		choice_b: Option<String>,
+		options: Option<Vec<String>>,
 	) -> Result<Round> {
-		let round = self.find_round_by_number_and_phase(number, phase)?;
-		if round.is_none() {
-			bail!("Could not find round");
-		}
-		let mut round = round.unwrap();
+		transaction(self, |_| {
+			let round = self.find_round_by_number_and_phase(room_id, number, phase)?;
+			if round.is_none() {
+				bail!("Could not find round");
+			}
+			let mut round = round.unwrap();
 
-		if let Some(new_state) = state {
-			round.state = new_state;
-		}
-		if let Some(new_question) = question {
-			round.question = new_question;
-		}
-		if let Some(new_choice_a) = choice_a {
-			round.choice_a = new_choice_a;
-		}
-		if let Some(new_choice_b) = choice_b {
-			round.choice_b = new_choice_b;
+			if let Some(new_state) = state {
+				round.state = new_state;
+			}
+			if let Some(new_question) = question {
+				round.question = new_question;
+			}
+			if let Some(new_options) = options {
+				if new_options != round.options {
+					self.set_round_options(round.id, &new_options)?;
+					round.options = new_options;
+				}
+			}
+
+			let mut statement = self.connection()?.prepare(
+				"UPDATE Rounds SET state = ?1, question = ?2, choice_a = ?3, choice_b = ?4
+					WHERE id = ?5",
+			)?;
+			statement.execute(params![
+				round.state,
+				round.question,
+				round.options.first().cloned().unwrap_or_default(),
+				round.options.get(1).cloned().unwrap_or_default(),
+				round.id
+			])?;
+
+			Ok(round)
+		})
+	}
+
+	fn mark_round_resolved(&self, round_id: i64) -> Result<bool> {
+		let changed = self.connection()?.execute(
+			"UPDATE Rounds SET resolved = TRUE WHERE id = ?1 AND NOT resolved",
+			params![round_id],
+		)?;
+
+		Ok(changed > 0)
+	}
+
+	fn is_round_resolved(&self, round_id: i64) -> Result<bool> {
+		let mut statement = self
+			.connection()?
+			.prepare("SELECT resolved FROM Rounds WHERE id = ?1")?;
+
+		let resolved = statement
+			.query_row(params![round_id], |row| row.get(0))
+			.optional()?;
+
+		Ok(resolved.unwrap_or(false))
+	}
+
+	fn replace_game_plan(&self, room_id: i64, plan: &[PlannedRound]) -> Result<()> {
+		let connection = self.connection()?;
+
+		// Half a plan is worse than the old one
+		transaction(self, |_| {
+			connection.execute(
+				"DELETE FROM PlannedRoundOptions WHERE planned_round_id IN
+					(SELECT id FROM PlannedRounds WHERE room_id = ?1)",
+				params![room_id],
+			)?;
+			connection.execute(
+				"DELETE FROM PlannedRounds WHERE room_id = ?1",
+				params![room_id],
+			)?;
+
+			let mut statement = connection.prepare(
+				"INSERT INTO PlannedRounds
+					(room_id, number, phase, question, choice_a, choice_b, voting_seconds)
+					VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+			)?;
+			let mut option_statement = connection.prepare(
+				"INSERT INTO PlannedRoundOptions (planned_round_id, position, text)
+					VALUES (?1, ?2, ?3)",
+			)?;
+			for round in plan {
+				statement.execute(params![
+					room_id,
+					round.number,
+					round.phase,
+					round.question,
+					round.options.first().cloned().unwrap_or_default(),
+					round.options.get(1).cloned().unwrap_or_default(),
+					round.voting_seconds
+				])?;
+
+				let planned_round_id = connection.last_insert_rowid();
+				for (position, text) in round.options.iter().enumerate() {
+					option_statement.execute(params![planned_round_id, position, text])?;
+				}
+			}
+
+			Ok(())
+		})
+	}
+
+	fn get_game_plan(&self, room_id: i64) -> Result<Vec<PlannedRound>> {
+		let mut plan = Vec::new();
+
+		let mut statement = self.connection()?.prepare(
+			"SELECT id, number, phase, question, voting_seconds
+				FROM PlannedRounds WHERE room_id = ?1 ORDER BY number, phase",
+		)?;
+
+		let mut query = statement.query(params![room_id])?;
+		while let Some(row) = query.next()? {
+			plan.push(PlannedRound {
+				number: row.get(1)?,
+				phase: row.get(2)?,
+				question: row.get(3)?,
+				options: self.get_planned_round_options(row.get(0)?)?,
+				voting_seconds: row.get(4)?,
+			});
 		}
 
-		let mut statement = self.connection.prepare(
-			"UPDATE Rounds SET state = ?1, question = ?2, choice_a = ?3, choice_b = ?4
-				WHERE number = ?5 AND phase = ?6",
+		Ok(plan)
+	}
+
+	fn find_planned_round(
+		&self,
+		room_id: i64,
+		number: u8,
+		phase: u8,
+	) -> Result<Option<PlannedRound>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT id, question, voting_seconds
+				FROM PlannedRounds WHERE room_id = ?1 AND number = ?2 AND phase = ?3",
 		)?;
-		statement.execute(params![
-			round.state,
-			round.question,
-			round.choice_a,
-			round.choice_b,
-			round.number,
-			round.phase
-		])?;
 
-		Ok(round)
+		let find = statement
+			.query_row(params![room_id, number, phase], |row| {
+				let id: i64 = row.get(0)?;
+				let question: String = row.get(1)?;
+				let voting_seconds: Option<u64> = row.get(2)?;
+				Ok((id, question, voting_seconds))
+			})
+			.optional()?;
+
+		let Some((id, question, voting_seconds)) = find else {
+			return Ok(None);
+		};
+
+		Ok(Some(PlannedRound {
+			number,
+			phase,
+			question,
+			options: self.get_planned_round_options(id)?,
+			voting_seconds,
+		}))
 	}
 
-	fn mark_choice(&self, choice_id: u8, lie: Option<bool>) -> Result<()> {
+	fn mark_choice(&self, choice_id: i64, lie: Option<bool>) -> Result<()> {
 		if lie.is_none() {
 			return Ok(());
 		}
 
 		let mut statement = self
-			.connection
+			.connection()?
 			.prepare("UPDATE Choices SET lie = ?1 WHERE id = ?2")?;
 		let update = statement.execute(params![lie.unwrap(), choice_id])?;
 
@@ -349,69 +693,59 @@ impl Database for SqliteDatabase {
 		Ok(())
 	}
 
-	fn find_choice_by_round_and_player(
+	fn update_or_create_choice(
 		&self,
-		round_id: u8,
-		player_id: u8,
-	) -> Result<Option<Choice>> {
-		let mut statement = self.connection.prepare(
-			"SELECT id, option, lie FROM Choices WHERE round_id = ?1 AND player_id = ?2",
-		)?;
+		round_id: i64,
+		player_id: i64,
+		option: ChoiceOption,
+	) -> Result<Choice> {
+		transaction(self, |_| {
+			let mut statement = self.connection()?.prepare(
+				"INSERT INTO Choices (round_id, player_id, option) VALUES (?1, ?2, ?3)
+					ON CONFLICT (round_id, player_id) DO UPDATE SET option = excluded.option
+					RETURNING id, option, lie",
+			)?;
 
-		let find = statement
-			.query_row(params![round_id, player_id], |row| {
+			let choice = statement.query_row(params![round_id, player_id, option], |row| {
 				Ok(Choice {
 					id: row.get(0)?,
 					option: row.get(1)?,
 					lie: row.get(2)?,
 				})
-			})
-			.optional()?;
+			})?;
 
-		Ok(find)
-	}
+			self.connection()?.execute(
+				"INSERT INTO ChoiceEvents (round_id, player_id, option, submitted_at)
+					VALUES (?1, ?2, ?3, ?4)",
+				params![round_id, player_id, option, Utc::now()],
+			)?;
 
-	fn update_or_create_choice(
-		&self,
-		round_id: u8,
-		player_id: u8,
-		option: ChoiceOption,
-	) -> Result<Choice> {
-		let find = self.find_choice_by_round_and_player(round_id, player_id)?;
-
-		let sql = match find {
-			Some(choice) => {
-				let mut statement = self
-					.connection
-					.prepare("UPDATE Choices SET option = ?1 WHERE id = ?2")?;
-				statement.execute(params![option, choice.id])
-			}
-			None => {
-				let mut statement = self.connection.prepare(
-					"INSERT INTO Choices (round_id, player_id, option) VALUES (?1, ?2, ?3)",
-				)?;
-				statement.execute(params![round_id, player_id, option])
-			}
-		};
+			Ok(choice)
+		})
+	}
 
-		if sql.is_err() {
-			bail!("Could not insert/update choice");
-		}
+	fn get_choice_events(&self, round_id: i64, player_id: i64) -> Result<Vec<ChoiceEvent>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT option, submitted_at FROM ChoiceEvents
+				WHERE round_id = ?1 AND player_id = ?2 ORDER BY id",
+		)?;
 
-		let refind = self
-			.find_choice_by_round_and_player(round_id, player_id)?
-			.ok_or(anyhow!("Could not refind the inserted/updated choice"))?;
+		let events = statement
+			.query_map(params![round_id, player_id], |row| {
+				let submitted_at: DateTime<Utc> = row.get(1)?;
+				Ok(ChoiceEvent {
+					option: row.get(0)?,
+					submitted_at: submitted_at.timestamp_millis(),
+				})
+			})?
+			.collect::<rusqlite::Result<Vec<ChoiceEvent>>>()?;
 
-		Ok(Choice {
-			id: refind.id,
-			option: refind.option,
-			lie: refind.lie,
-		})
+		Ok(events)
 	}
 
-	fn check_player_is_allowed_to_vote(&self, player_id: u8) -> Result<bool> {
+	fn check_player_is_allowed_to_vote(&self, player_id: i64) -> Result<bool> {
 		let mut statement = self
-			.connection
+			.connection()?
 			.prepare("SELECT can_vote FROM Players WHERE id = ?1")?;
 
 		let find = statement.query_row(params![player_id], |row| {
@@ -427,9 +761,30 @@ impl Database for SqliteDatabase {
 }
 
 impl SqliteDatabase {
-	fn get_round_by_id(&self, id: u8) -> Result<Option<Round>> {
-		let mut statement = self.connection.prepare(
-			"SELECT id, number, phase, state, question, choice_a, choice_b
+	// Makes the new game the current one of its room
+	fn insert_game(&self, room_id: i64, name: &str) -> Result<Game> {
+		let connection = self.connection()?;
+		connection.execute(
+			"INSERT INTO Games (room_id, name, started_at) VALUES (?1, ?2, ?3)",
+			params![room_id, name, Utc::now()],
+		)?;
+
+		let game_id = connection.last_insert_rowid();
+		connection.execute(
+			"UPDATE Rooms SET current_game_id = ?1 WHERE id = ?2",
+			params![game_id, room_id],
+		)?;
+
+		let mut statement = connection
+			.prepare("SELECT id, name, started_at, archived_at FROM Games WHERE id = ?1")?;
+		let game = statement.query_row(params![game_id], game_from_row)?;
+
+		Ok(game)
+	}
+
+	fn get_round_by_id(&self, id: i64) -> Result<Option<Round>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT id, number, phase, state, question
 				FROM Rounds WHERE id = ?1",
 		)?;
 
@@ -441,14 +796,70 @@ impl SqliteDatabase {
 					phase: row.get(2)?,
 					state: row.get(3)?,
 					question: row.get(4)?,
-					choice_a: row.get(5)?,
-					choice_b: row.get(6)?,
+					options: vec![],
 				})
 			})
 			.optional()?;
 
-		Ok(find)
+		let Some(mut round) = find else {
+			return Ok(None);
+		};
+		round.options = self.get_round_options(round.id)?;
+
+		Ok(Some(round))
 	}
+
+	fn get_round_options(&self, round_id: i64) -> Result<Vec<String>> {
+		let mut statement = self
+			.connection()?
+			.prepare("SELECT text FROM RoundOptions WHERE round_id = ?1 ORDER BY position")?;
+
+		let options = statement
+			.query_map(params![round_id], |row| row.get(0))?
+			.collect::<rusqlite::Result<Vec<String>>>()?;
+
+		Ok(options)
+	}
+
+	fn set_round_options(&self, round_id: i64, options: &[String]) -> Result<()> {
+		self.connection()?.execute(
+			"DELETE FROM RoundOptions WHERE round_id = ?1",
+			params![round_id],
+		)?;
+
+		let mut statement = self
+			.connection()?
+			.prepare("INSERT INTO RoundOptions (round_id, position, text) VALUES (?1, ?2, ?3)")?;
+		for (position, text) in options.iter().enumerate() {
+			statement.execute(params![round_id, position, text])?;
+		}
+
+		Ok(())
+	}
+
+	fn get_planned_round_options(&self, planned_round_id: i64) -> Result<Vec<String>> {
+		let mut statement = self.connection()?.prepare(
+			"SELECT text FROM PlannedRoundOptions WHERE planned_round_id = ?1 ORDER BY position",
+		)?;
+
+		let options = statement
+			.query_map(params![planned_round_id], |row| row.get(0))?
+			.collect::<rusqlite::Result<Vec<String>>>()?;
+
+		Ok(options)
+	}
+}
+
+fn game_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Game> {
+	let started_at: DateTime<Utc> = row.get(2)?;
+	let archived_at: Option<DateTime<Utc>> = row.get(3)?;
+
+	Ok(Game {
+		id: row.get(0)?,
+		name: row.get(1)?,
+		started_at: started_at.timestamp_millis(),
+		archived_at: archived_at.map(|archived_at| archived_at.timestamp_millis()),
+	})
 }
 
 impl ToSql for RoundState {
@@ -484,19 +895,12 @@ impl FromSql for RoundState {
 
 impl ToSql for ChoiceOption {
 	fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
-		match self {
-			ChoiceOption::ChoiceA => Ok("a".into()),
-			ChoiceOption::ChoiceB => Ok("b".into()),
-		}
+		Ok(self.letter().to_string().into())
 	}
 }
 
 impl FromSql for ChoiceOption {
 	fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
-		match value.as_str()? {
-			"a" => Ok(ChoiceOption::ChoiceA),
-			"b" => Ok(ChoiceOption::ChoiceB),
-			_ => Err(FromSqlError::Other(Box::new(Error))),
-		}
+		ChoiceOption::from_letter(value.as_str()?).ok_or(FromSqlError::Other(Box::new(Error)))
 	}
 }
@@ -0,0 +1,67 @@
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use ts_rs::TS;
+
+// Machine-readable reason sent along with every "ng" response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+	ParseError,
+	UnknownAction,
+	UnsupportedVersion,
+	Forbidden,
+	InvalidState,
+	InvalidInput,
+	NotFound,
+	Conflict,
+	Internal,
+}
+
+impl ErrorCode {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			ErrorCode::ParseError => "parse_error",
+			ErrorCode::UnknownAction => "unknown_action",
+			ErrorCode::UnsupportedVersion => "unsupported_version",
+			ErrorCode::Forbidden => "forbidden",
+			ErrorCode::InvalidState => "invalid_state",
+			ErrorCode::InvalidInput => "invalid_input",
+			ErrorCode::NotFound => "not_found",
+			ErrorCode::Conflict => "conflict",
+			ErrorCode::Internal => "internal",
+		}
+	}
+}
+
+// A failure that is reported back to the client instead of only being logged
+#[derive(Debug, Clone)]
+pub struct GameError {
+	pub code: ErrorCode,
+	pub message: String,
+}
+
+impl GameError {
+	pub fn new(code: ErrorCode, message: impl Into<String>) -> GameError {
+		GameError {
+			code,
+			message: message.into(),
+		}
+	}
+
+	pub fn internal() -> GameError {
+		GameError::new(
+			ErrorCode::Internal,
+			"The server could not handle the request.",
+		)
+	}
+}
+
+impl fmt::Display for GameError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.message)
+	}
+}
+
+impl std::error::Error for GameError {}
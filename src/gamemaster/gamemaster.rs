@@ -1,127 +1,394 @@
-use std::{collections::HashMap, net::SocketAddr};
+use std::{
+	net::SocketAddr,
+	time::{Duration, Instant as StdInstant},
+};
 
 use anyhow::{anyhow, bail, Result};
-use crossbeam::channel::{Receiver, Sender};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use tokio::{
+	sync::mpsc::{Receiver, Sender},
+	time::{sleep_until, Instant},
+};
 
 use crate::{
-	database::database::DatabaseAccess,
+	config::config::ConfigAccess,
+	database::database::{transaction, DatabaseAccess},
 	gamemaster::{
 		helpers::{
 			acquire_database_lock, allow_all_active_players_to_vote, announce_active_players,
-			announce_choice_to_organizers, announce_round, announce_updated_choices,
-			announce_updated_player, compile_choices, compile_game_state, get_active_round,
-			get_individual_channel_sender, is_organizer, is_player,
+			announce_choice_to_organizers, announce_game_plan_to_organizers, announce_game_state,
+			announce_games_to_organizers, announce_round, announce_round_result, announce_timer,
+			announce_updated_choices, announce_updated_player, apply_round_result,
+			check_choices_open, check_round_options, check_round_transition, compile_choices,
+			compile_game_state, compute_round_result, disconnect_lagging_clients,
+			find_client_room_id, generate_room_code, get_active_round, get_client_room,
+			get_individual_channel_sender, get_organizer, is_display, is_player,
+			issue_session_token, load_room, move_client, reissue_session_tokens, reload_players,
+			report_error, send_shutdown_notice,
 		},
+		plan::{parse_game_plan, PlanFormat},
+		timer::{RoundTimer, MAX_DURATION},
 		types::RoundState,
 	},
 	postmaster::types::{InternalMessage, InternalMessageAction, ResponseIdentifier},
 };
 
-use super::types::{ChoiceOption, Client, ClientStatus, ClientsMap, Organizer, Round};
+use super::{
+	credentials::verify_password,
+	error::{ErrorCode, GameError},
+	outbox::ClientOutbox,
+	types::{
+		ChoiceOption, Client, ClientStatus, ClientsMap, GameRoom, GamemasterState, Room, RoomsMap,
+		Round, SessionsMap, DEFAULT_ROOM_ID,
+	},
+};
 
-pub async fn start_gamemaster(
-	gm_channel_receiver: Receiver<InternalMessage>,
-	database: DatabaseAccess,
+pub(super) async fn run_gamemaster(
+	gm_channel_receiver: &mut Receiver<InternalMessage>,
+	state: &mut GamemasterState,
+	database: &DatabaseAccess,
+	config: &ConfigAccess,
 ) -> Result<()> {
-	let mut clients: ClientsMap = HashMap::new();
-
 	loop {
-		let received_message = gm_channel_receiver.recv();
-		if received_message.is_err() {
-			error!("Error when trying to receive channel message");
-			break;
-		}
-
-		let received_message = received_message.unwrap();
-		let response = match received_message.payload {
-			InternalMessageAction::RequestRegisterClient(address, individual_channel_sender) => {
-				let r = process_register_client(&mut clients, address, individual_channel_sender);
-				Some(r)
-			}
-			InternalMessageAction::RequestRegisterActivePlayer(address, name) => {
-				let r = process_register_active_player(
-					&database,
-					&mut clients,
-					address,
-					received_message.response_id,
-					name,
-				);
-				Some(r)
-			}
-			InternalMessageAction::RequestRegisterOrganizer(address, password) => {
-				let r = process_register_organizer(
-					&mut clients,
-					address,
-					received_message.response_id,
-					password,
-				);
-				Some(r)
-			}
-			InternalMessageAction::ExitClient(address) => {
-				let r = process_exit_client(&mut clients, address);
-				Some(r)
-			}
-			InternalMessageAction::RequestGameState(address) => {
-				let r = process_retrieve_game_state(
-					&database,
-					&clients,
-					address,
-					received_message.response_id,
-				);
-				Some(r)
-			}
-			InternalMessageAction::RequestSetRound(address, round) => {
-				let r = process_set_round(
-					&database,
-					&clients,
-					address,
-					received_message.response_id,
-					round,
-				);
-				Some(r)
-			}
-			InternalMessageAction::RequestSetChoiceOption(address, choice) => {
-				let r = process_set_choice(
-					&database,
-					&clients,
-					address,
-					received_message.response_id,
-					choice,
-				);
-				Some(r)
-			}
-			InternalMessageAction::RequestMarkPlayer(address, id, points, can_vote) => {
-				let r = process_mark_player(
-					&database,
-					&clients,
-					address,
-					received_message.response_id,
-					id,
-					points,
-					can_vote,
-				);
-				Some(r)
+		// Every room runs its own timer, wake up for whichever is due first
+		let timer_wake_up = state
+			.rooms
+			.values()
+			.filter_map(|game_room| game_room.timer.as_ref()?.next_wake_up())
+			.min();
+
+		let received_message = tokio::select! {
+			received_message = gm_channel_receiver.recv() => match received_message {
+				Some(received_message) => received_message,
+				None => break,
+			},
+
+			// Only polled while a timer is running
+			_ = sleep_until(Instant::from_std(timer_wake_up.unwrap_or_else(StdInstant::now))), if timer_wake_up.is_some() => {
+				let now = StdInstant::now();
+				for GameRoom { room, clients, timer } in state.rooms.values_mut() {
+					let is_due = timer
+						.as_ref()
+						.and_then(|timer| timer.next_wake_up())
+						.is_some_and(|wake_up| wake_up <= now);
+					if !is_due {
+						continue;
+					}
+
+					if let Err(error) = process_timer(database, config, room.id, clients, timer) {
+						error!("{}", error);
+					}
+				}
+
+				disconnect_lagging_clients(config, &mut state.rooms);
+				continue;
 			}
-			InternalMessageAction::RequestMarkChoice(address, id, lie) => {
-				let r = process_mark_choice(
-					&database,
-					&clients,
-					address,
-					received_message.response_id,
-					id,
-					lie,
-				);
-				Some(r)
-			}
-			_ => None,
 		};
 
-		// Log a warning to output if the processing did not return Ok
-		if let Some(response) = response {
-			if response.is_err() {
-				error!("{}", response.unwrap_err());
+		// Whoever sent a failed request gets an "ng" instead of waiting for their timeout
+		let requester = received_message.payload.requester();
+		let response_id = received_message.response_id.clone();
+		if let Err(error) = dispatch_message(database, config, state, received_message) {
+			report_error(&state.rooms, requester, response_id, error);
+		}
+
+		disconnect_lagging_clients(config, &mut state.rooms);
+
+		// Every connection has said goodbye, nothing else is coming
+		let is_empty = state
+			.rooms
+			.values()
+			.all(|game_room| game_room.clients.is_empty());
+		if state.shutting_down && is_empty {
+			info!("All clients disconnected, gamemaster is stopping");
+			return Ok(());
+		}
+	}
+
+	error!("Gamemaster channel closed");
+
+	Ok(())
+}
+
+// Hand a message to its process function, along with the room of the client that sent it
+fn dispatch_message(
+	database: &DatabaseAccess,
+	config: &ConfigAccess,
+	state: &mut GamemasterState,
+	received_message: InternalMessage,
+) -> Result<()> {
+	let GamemasterState {
+		rooms,
+		sessions,
+		shutting_down,
+	} = state;
+	let response_id = received_message.response_id;
+
+	match received_message.payload {
+		InternalMessageAction::RequestRegisterClient(address, individual_channel_sender) => {
+			// Everyone starts out in the default room
+			let GameRoom { clients, .. } = load_room(database, rooms, DEFAULT_ROOM_ID)?;
+			let r = process_register_client(clients, address, individual_channel_sender);
+
+			// Connections accepted right before the shutdown are turned away immediately
+			if *shutting_down {
+				send_shutdown_notice(clients, &address);
 			}
+
+			r
+		}
+		InternalMessageAction::RequestShutdown => {
+			*shutting_down = true;
+			process_shutdown(rooms)
+		}
+		InternalMessageAction::RequestRegisterActivePlayer(address, name) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_register_active_player(
+				database,
+				config,
+				room.id,
+				clients,
+				sessions,
+				address,
+				response_id,
+				name,
+			)
+		}
+		InternalMessageAction::RequestResumeSession(address, session_token) => {
+			process_resume_session(
+				database,
+				config,
+				rooms,
+				sessions,
+				address,
+				response_id,
+				session_token,
+			)
+		}
+		InternalMessageAction::RequestRegisterOrganizer(address, username, password) => {
+			let GameRoom { clients, .. } = get_client_room(rooms, &address)?;
+			process_register_organizer(database, clients, address, response_id, username, password)
+		}
+		InternalMessageAction::RequestRegisterDisplay(address) => {
+			let GameRoom {
+				room,
+				clients,
+				timer,
+			} = get_client_room(rooms, &address)?;
+			process_register_display(database, room, clients, timer, address, response_id)
+		}
+		InternalMessageAction::RequestJoinRoom(address, code) => {
+			process_join_room(database, rooms, address, response_id, code)
+		}
+		InternalMessageAction::RequestCreateRoom(address, name) => {
+			process_create_room(database, rooms, address, response_id, name)
+		}
+		InternalMessageAction::RequestRooms(address) => {
+			let GameRoom { clients, .. } = get_client_room(rooms, &address)?;
+			process_retrieve_rooms(database, clients, address, response_id)
+		}
+		InternalMessageAction::RequestStartGame(address, name) => {
+			let GameRoom {
+				room,
+				clients,
+				timer,
+			} = get_client_room(rooms, &address)?;
+			process_start_game(
+				database,
+				config,
+				room,
+				clients,
+				timer,
+				sessions,
+				address,
+				response_id,
+				name,
+			)
+		}
+		InternalMessageAction::RequestGames(address) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_retrieve_games(database, room.id, clients, address, response_id)
+		}
+		InternalMessageAction::ExitClient(address) => {
+			// Lagging clients are already gone by the time they say goodbye
+			let Some(room_id) = find_client_room_id(rooms, &address) else {
+				return Ok(());
+			};
+			let GameRoom { clients, .. } = load_room(database, rooms, room_id)?;
+			process_exit_client(clients, address)
+		}
+		InternalMessageAction::RequestGameState(address) => {
+			let GameRoom {
+				room,
+				clients,
+				timer,
+			} = get_client_room(rooms, &address)?;
+			process_retrieve_game_state(database, room, clients, timer, address, response_id)
+		}
+		InternalMessageAction::RequestSetRound(address, round, override_transition) => {
+			let GameRoom {
+				room,
+				clients,
+				timer,
+			} = get_client_room(rooms, &address)?;
+			process_set_round(
+				database,
+				config,
+				room.id,
+				clients,
+				timer,
+				address,
+				response_id,
+				round,
+				override_transition,
+			)
+		}
+		InternalMessageAction::RequestAdvanceRoundState(address) => {
+			let GameRoom {
+				room,
+				clients,
+				timer,
+			} = get_client_room(rooms, &address)?;
+			process_advance_round_state(
+				database,
+				config,
+				room.id,
+				clients,
+				timer,
+				address,
+				response_id,
+			)
+		}
+		InternalMessageAction::RequestLoadPlan(address, format, content) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_load_plan(
+				database,
+				room.id,
+				clients,
+				address,
+				response_id,
+				format,
+				&content,
+			)
+		}
+		InternalMessageAction::RequestGamePlan(address) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_retrieve_game_plan(database, room.id, clients, address, response_id)
+		}
+		InternalMessageAction::RequestNextRound(address) => {
+			let GameRoom {
+				room,
+				clients,
+				timer,
+			} = get_client_room(rooms, &address)?;
+			process_next_round(
+				database,
+				config,
+				room.id,
+				clients,
+				timer,
+				address,
+				response_id,
+			)
+		}
+		InternalMessageAction::RequestStartTimer(address, seconds, advance_to) => {
+			let GameRoom {
+				room,
+				clients,
+				timer,
+			} = get_client_room(rooms, &address)?;
+			process_start_timer(
+				database,
+				room.id,
+				clients,
+				timer,
+				address,
+				response_id,
+				seconds,
+				advance_to,
+			)
+		}
+		InternalMessageAction::RequestPauseTimer(address) => {
+			let GameRoom { clients, timer, .. } = get_client_room(rooms, &address)?;
+			process_pause_timer(clients, timer, address, response_id)
+		}
+		InternalMessageAction::RequestResumeTimer(address) => {
+			let GameRoom { clients, timer, .. } = get_client_room(rooms, &address)?;
+			process_resume_timer(clients, timer, address, response_id)
+		}
+		InternalMessageAction::RequestExtendTimer(address, seconds) => {
+			let GameRoom { clients, timer, .. } = get_client_room(rooms, &address)?;
+			process_extend_timer(clients, timer, address, response_id, seconds)
+		}
+		InternalMessageAction::RequestCancelTimer(address) => {
+			let GameRoom { clients, timer, .. } = get_client_room(rooms, &address)?;
+			process_cancel_timer(clients, timer, address, response_id)
+		}
+		InternalMessageAction::RequestSetChoiceOption(address, choice) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_set_choice(database, room.id, clients, address, response_id, choice)
+		}
+		InternalMessageAction::RequestMarkPlayer(address, id, points, can_vote) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_mark_player(
+				database,
+				room.id,
+				clients,
+				address,
+				response_id,
+				id,
+				points,
+				can_vote,
+			)
+		}
+		InternalMessageAction::RequestSetLateVote(address, player_id, option) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_set_late_vote(
+				database,
+				room.id,
+				clients,
+				address,
+				response_id,
+				player_id,
+				option,
+			)
+		}
+		InternalMessageAction::RequestMarkChoice(address, id, lie) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_mark_choice(database, room.id, clients, address, response_id, id, lie)
+		}
+		InternalMessageAction::RequestVoteHistory(address, round_id, player_id) => {
+			let GameRoom { room, clients, .. } = get_client_room(rooms, &address)?;
+			process_retrieve_vote_history(
+				database,
+				room.id,
+				clients,
+				address,
+				response_id,
+				round_id,
+				player_id,
+			)
+		}
+		_ => Ok(()),
+	}
+}
+
+fn process_shutdown(rooms: &mut RoomsMap) -> Result<()> {
+	debug!("===== Shutdown");
+
+	let connections: usize = rooms
+		.values()
+		.map(|game_room| game_room.clients.len())
+		.sum();
+	info!(
+		"Server is shutting down, closing {} connections",
+		connections
+	);
+
+	for GameRoom { clients, .. } in rooms.values_mut() {
+		let addresses: Vec<SocketAddr> = clients.keys().copied().collect();
+		for address in addresses {
+			send_shutdown_notice(clients, &address);
 		}
 	}
 
@@ -135,9 +402,9 @@ fn process_register_client(
 ) -> Result<()> {
 	debug!("===== Register client");
 	clients.insert(
-		address.clone(),
+		address,
 		Client {
-			individual_channel_sender,
+			individual_channel_sender: ClientOutbox::new(individual_channel_sender),
 			status: ClientStatus::Unregistered,
 			player: None,
 			organizer: None,
@@ -156,24 +423,33 @@ fn process_register_client(
 	Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn process_register_active_player(
 	database: &DatabaseAccess,
+	config: &ConfigAccess,
+	room_id: i64,
 	clients: &mut ClientsMap,
+	sessions: &mut SessionsMap,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
 	name: String,
 ) -> Result<()> {
 	debug!("===== Register player");
 
-	// Limit player name to 12 characters
-	let trimmed_name = &name[0..std::cmp::min(name.len(), 12)];
+	if is_display(clients, &address) {
+		let message = "A display cannot log in as a player.".to_owned();
+		bail!(GameError::new(ErrorCode::Forbidden, message));
+	}
+
+	// Limit player name length
+	let trimmed_name: String = name.chars().take(config.max_player_name_length).collect();
 
 	let player = {
 		let db_access = acquire_database_lock(database)?;
 
 		// Find player data in database
 		debug!("Finding/creating player ({})...", &trimmed_name);
-		db_access.find_or_create_player(&trimmed_name)
+		db_access.find_or_create_player(room_id, &trimmed_name)
 	}?;
 
 	// Loop clients, see if player already exists
@@ -186,16 +462,10 @@ fn process_register_active_player(
 		}
 
 		if iter_client.player.as_ref().unwrap().id == player.id {
-			// Conflict
-			let ics = get_individual_channel_sender(clients, &address)?;
-			ics.send(InternalMessage {
-				payload: InternalMessageAction::ResponseNotOkay(
-					"That player is already connected on a different device.".to_owned(),
-				),
-				response_id,
-				..Default::default()
-			})?;
-			return Ok(());
+			bail!(GameError::new(
+				ErrorCode::Conflict,
+				"That player is already connected on a different device.",
+			));
 		}
 	}
 
@@ -206,296 +476,1506 @@ fn process_register_active_player(
 	});
 	info!("Connected players updated");
 
-	// Send response confirming identity
-	let ics = get_individual_channel_sender(&clients, &address)?;
+	// Send response confirming identity along with a token for resuming later
+	let session_token = issue_session_token(config, sessions, room_id, player.id);
+	let ics = get_individual_channel_sender(clients, &address)?;
 	ics.send(InternalMessage {
-		payload: InternalMessageAction::ResponsePlayerIdentity(player),
+		payload: InternalMessageAction::ResponsePlayerIdentity(player, session_token),
 		response_id,
-		..Default::default()
 	})?;
 	debug!("Response (player identity confirmation) sent");
 
 	// Announce to other clients
-	announce_active_players(&clients);
+	announce_active_players(clients);
 
 	Ok(())
 }
 
-fn process_register_organizer(
-	clients: &mut ClientsMap,
+#[allow(clippy::too_many_arguments)]
+fn process_resume_session(
+	database: &DatabaseAccess,
+	config: &ConfigAccess,
+	rooms: &mut RoomsMap,
+	sessions: &mut SessionsMap,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
-	password: String,
+	session_token: String,
 ) -> Result<()> {
-	debug!("===== Register organizer");
+	debug!("===== Resume session");
 
-	// Check if organizer key is valid
-	let the_password = "minorityrule"; // TODO do not hardcode this
-	if !password.eq(the_password) {
-		let ics = get_individual_channel_sender(&clients, &address)?;
-		ics.send(InternalMessage {
-			payload: InternalMessageAction::ResponseNotOkay(
-				"The organizer password is incorrect.".to_owned(),
-			),
-			response_id,
-			..Default::default()
-		})?;
-		return Ok(());
+	let GameRoom { clients, .. } = get_client_room(rooms, &address)?;
+	if is_display(clients, &address) {
+		let message = "A display cannot log in as a player.".to_owned();
+		bail!(GameError::new(ErrorCode::Forbidden, message));
+	}
+	if get_organizer(clients, &address).is_some() {
+		let message = "An organizer cannot take over a player's session.".to_owned();
+		bail!(GameError::new(ErrorCode::Forbidden, message));
 	}
 
-	// Make up some name and update clients map
-	let organizer = Organizer {
-		name: random_word::gen(random_word::Lang::En).to_owned(),
+	let session = sessions
+		.remove(&session_token)
+		.filter(|session| session.expires_at > StdInstant::now());
+	let Some(session) = session else {
+		bail!(GameError::new(
+			ErrorCode::NotFound,
+			"Your session has expired. Please log in again.",
+		));
 	};
+
+	let player = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.find_player_by_id(session.room_id, session.player_id)
+	}?
+	.ok_or(anyhow!("Could not find the player of session"))?;
+
+	// The session knows which room the player was playing in
+	move_client(database, rooms, address, session.room_id)?;
+	let GameRoom {
+		room,
+		clients,
+		timer,
+	} = load_room(database, rooms, session.room_id)?;
+
+	// Replace any stale connection still holding this player
+	let stale_addresses: Vec<SocketAddr> = clients
+		.iter()
+		.filter(|(iter_address, iter_client)| {
+			**iter_address != address
+				&& iter_client
+					.player
+					.as_ref()
+					.is_some_and(|iter_player| iter_player.id == player.id)
+		})
+		.map(|(iter_address, _)| *iter_address)
+		.collect();
+	for stale_address in stale_addresses {
+		if let Some(stale_client) = clients.remove(&stale_address) {
+			let _ = stale_client
+				.individual_channel_sender
+				.send(InternalMessage {
+					payload: InternalMessageAction::ResponseNotOkay(GameError::new(
+						ErrorCode::Conflict,
+						"This session was resumed on another connection.",
+					)),
+					..Default::default()
+				});
+		}
+		info!(
+			"Replaced stale connection {} of player {}",
+			stale_address, player.name
+		);
+	}
+
 	clients.entry(address).and_modify(|c| {
 		c.status = ClientStatus::Registered;
-		c.organizer = Some(organizer.clone());
+		c.player = Some(player.clone());
 	});
-	debug!("Marked client as organizer");
+	info!(
+		"Player {} resumed session in room {} from {}",
+		player.name, room.code, address
+	);
 
-	let ics = get_individual_channel_sender(&clients, &address)?;
+	// The token that was used is spent, the player gets a new one
+	let session_token = issue_session_token(config, sessions, session.room_id, player.id);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
 	ics.send(InternalMessage {
-		payload: InternalMessageAction::ResponseOrganizerIdentity(organizer),
-		response_id: response_id,
+		payload: InternalMessageAction::ResponsePlayerIdentity(player, session_token),
+		response_id,
 	})?;
-	debug!("Response (organizer identity confirmation) sent");
-
-	Ok(())
-}
-
-fn process_exit_client(clients: &mut ClientsMap, address: SocketAddr) -> Result<()> {
-	debug!("===== Exit client");
 
-	clients.remove(&address);
-	debug!("Removed client: {}", address);
+	// Bring the player up to date right away
+	let game_state = compile_game_state(database, room, clients, timer, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseGameState(Box::new(game_state)),
+		..Default::default()
+	})?;
 
 	announce_active_players(clients);
 
 	Ok(())
 }
 
-fn process_retrieve_game_state(
+fn process_join_room(
 	database: &DatabaseAccess,
-	clients: &ClientsMap,
+	rooms: &mut RoomsMap,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
+	code: String,
 ) -> Result<()> {
-	debug!("===== Retrieve game state");
+	debug!("===== Join room");
 
-	let game_state = compile_game_state(database, clients)?;
+	let GameRoom { clients, .. } = get_client_room(rooms, &address)?;
 
-	let ics = get_individual_channel_sender(&clients, &address)?;
-	ics.send(InternalMessage {
-		payload: InternalMessageAction::ResponseGameState(game_state),
-		response_id,
-		..Default::default()
-	})?;
+	// Players keep their points and votes in the room they logged in to
+	if is_player(clients, &address) {
+		let message = "Players cannot switch rooms after logging in.".to_owned();
+		bail!(GameError::new(ErrorCode::Forbidden, message));
+	}
 
-	Ok(())
+	let code = code.trim().to_uppercase();
+	let room = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.find_room_by_code(&code)?
+	};
+	let Some(room) = room else {
+		let message = format!("There is no room with the code {}.", code);
+		bail!(GameError::new(ErrorCode::NotFound, message));
+	};
+
+	enter_room(database, rooms, address, response_id, room.id)
 }
 
-fn process_set_round(
+fn process_create_room(
 	database: &DatabaseAccess,
-	clients: &ClientsMap,
+	rooms: &mut RoomsMap,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
-	round: Round,
+	name: String,
 ) -> Result<()> {
-	debug!("===== Set round");
+	debug!("===== Create room");
+
+	let GameRoom { clients, .. } = get_client_room(rooms, &address)?;
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can create rooms."
+		));
+	};
+	let organizer = organizer.clone();
 
-	if !is_organizer(clients, &address) {
-		bail!("Set round request came from a non-organizer");
+	let name = name.trim();
+	if name.is_empty() {
+		let message = "The room needs a name.".to_owned();
+		bail!(GameError::new(ErrorCode::InvalidInput, message));
 	}
 
-	let is_new_round;
-
-	let round = {
+	let room = {
 		let db_access = acquire_database_lock(database)?;
-		let find_round = db_access.find_round_by_number_and_phase(round.number, round.phase)?;
 
-		let updated_round = match find_round {
-			Some(db_round) => {
-				is_new_round = false;
-				db_access.update_round(
-					db_round.number,
-					db_round.phase,
-					Some(round.state),
-					Some(round.question),
-					Some(round.choice_a),
-					Some(round.choice_b),
-				)?
-			}
-			None => {
-				is_new_round = true;
-				db_access.create_round(
-					round.number,
-					round.phase,
-					round.state,
-					round.question,
-					round.choice_a,
-					round.choice_b,
-				)?
-			}
-		};
+		// Codes are random, try again on the rare collision
+		let mut code = generate_room_code();
+		while db_access.find_room_by_code(&code)?.is_some() {
+			code = generate_room_code();
+		}
 
-		updated_round
+		db_access.create_room(&code, name)?
 	};
+	info!(
+		"Organizer {} created room {} ({})",
+		organizer.username, room.code, room.name
+	);
 
-	// Announce updated round
-	announce_round(database, clients, Some(round.clone()));
+	enter_room(database, rooms, address, response_id, room.id)
+}
 
-	// Announce choices if the round is new or in a state where choices are being shown
-	if is_new_round
-		|| round.state == RoundState::ShowVotes
-		|| round.state == RoundState::VotingTime
-		|| round.state == RoundState::VotingLocked
-		|| round.state == RoundState::ShowResults
-	{
-		let updated_choices = compile_choices(database, &round)?;
-		announce_updated_choices(clients, updated_choices);
-	}
+fn process_retrieve_rooms(
+	database: &DatabaseAccess,
+	clients: &ClientsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Retrieve rooms");
 
-	// Set can_vote to true to all active players if it's the standby state of a new round
-	if round.phase == 1 && round.state == RoundState::Standby {
-		allow_all_active_players_to_vote(database, clients)?;
+	if get_organizer(clients, &address).is_none() {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can list the rooms."
+		));
 	}
 
-	let ics = get_individual_channel_sender(&clients, &address)?;
+	let list = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.get_rooms()?
+	};
+
+	let ics = get_individual_channel_sender(clients, &address)?;
 	ics.send(InternalMessage {
-		payload: InternalMessageAction::ResponseOkay,
+		payload: InternalMessageAction::ResponseRooms(list),
 		response_id,
-		..Default::default()
 	})?;
 
 	Ok(())
 }
 
-fn process_set_choice(
+#[allow(clippy::too_many_arguments)]
+fn process_start_game(
 	database: &DatabaseAccess,
-	clients: &ClientsMap,
+	config: &ConfigAccess,
+	room: &Room,
+	clients: &mut ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	sessions: &mut SessionsMap,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
-	option: ChoiceOption,
+	name: String,
 ) -> Result<()> {
-	debug!("===== Set choice");
+	debug!("===== Start game");
 
-	if !is_player(clients, &address) {
-		bail!("Set choice request came from a non-player");
-	}
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can start a new game."
+		));
+	};
+	let organizer = organizer.clone();
 
-	let round = {
+	let (game, games) = {
 		let db_access = acquire_database_lock(database)?;
-		db_access.get_active_round()?
-	};
-	if round.is_none() {
-		bail!("Trying to set choice when no round is active");
-	}
-	let round = round.unwrap();
 
-	let client = clients.get(&address);
-	if client.is_none() {
-		bail!("Could not find the client");
-	}
-	let player = client.unwrap().clone().player;
-	if player.is_none() {
-		bail!("The client is not a player");
-	}
-	let player = player.unwrap();
+		let name = match name.trim() {
+			"" => format!("Game {}", db_access.get_games(room.id)?.len() + 1),
+			name => name.to_owned(),
+		};
+		let game = db_access.start_game(room.id, &name)?;
+		// Only once the new game is stored, so a failed start leaves the cached players alone
+		reload_players(&*db_access, room.id, clients)?;
 
-	let is_player_allowed_to_vote = {
-		let db_access = acquire_database_lock(database)?;
-		db_access.check_player_is_allowed_to_vote(player.id)?
+		(game, db_access.get_games(room.id)?)
 	};
+	info!(
+		"Organizer {} started {} in room {}",
+		organizer.username, game.name, room.code
+	);
 
-	if is_player_allowed_to_vote == false {
-		let ics = get_individual_channel_sender(&clients, &address)?;
-		ics.send(InternalMessage {
-			payload: InternalMessageAction::ResponseNotOkay(
-				"You are not allowed to vote.".to_owned(),
-			),
-			response_id,
-			..Default::default()
-		})?;
-		return Ok(());
+	// The timer belonged to a round of the archived game
+	if timer.take().is_some() {
+		announce_timer(clients, timer);
 	}
+	announce_game_state(database, room, clients, timer);
+	announce_active_players(clients);
+	announce_games_to_organizers(clients, games);
+	reissue_session_tokens(config, sessions, room.id, clients);
 
-	let set_choice = {
-		let db_access = acquire_database_lock(database)?;
-		db_access.update_or_create_choice(round.id, player.id, option)
-	}?;
-
-	announce_choice_to_organizers(clients, player, set_choice);
-
-	let ics = get_individual_channel_sender(&clients, &address)?;
+	let ics = get_individual_channel_sender(clients, &address)?;
 	ics.send(InternalMessage {
 		payload: InternalMessageAction::ResponseOkay,
 		response_id,
-		..Default::default()
 	})?;
 
 	Ok(())
 }
 
-fn process_mark_choice(
+fn process_retrieve_games(
 	database: &DatabaseAccess,
+	room_id: i64,
 	clients: &ClientsMap,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
-	id: u8,
-	lie: Option<bool>,
 ) -> Result<()> {
-	debug!("===== Mark choice");
+	debug!("===== Retrieve games");
 
-	if !is_organizer(clients, &address) {
-		bail!("Mark choice request came from a non-organizer");
+	if get_organizer(clients, &address).is_none() {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can list the games."
+		));
 	}
 
-	let round = get_active_round(database)?;
-	{
+	let games = {
 		let db_access = acquire_database_lock(database)?;
-		db_access.mark_choice(id, lie)
-	}?;
+		db_access.get_games(room_id)?
+	};
 
-	let updated_choices = compile_choices(database, &round)?;
-	announce_updated_choices(clients, updated_choices);
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseGames(games),
+		response_id,
+	})?;
+
+	Ok(())
+}
 
-	let ics = get_individual_channel_sender(&clients, &address)?;
+// Move a client over to another room and catch it up with the game running there
+fn enter_room(
+	database: &DatabaseAccess,
+	rooms: &mut RoomsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	room_id: i64,
+) -> Result<()> {
+	move_client(database, rooms, address, room_id)?;
+	let GameRoom {
+		room,
+		clients,
+		timer,
+	} = load_room(database, rooms, room_id)?;
+	info!("Client {} entered room {}", address, room.code);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
 	ics.send(InternalMessage {
-		payload: InternalMessageAction::ResponseOkay,
+		payload: InternalMessageAction::ResponseRoom(room.clone()),
 		response_id,
+	})?;
+
+	let game_state = compile_game_state(database, room, clients, timer, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseGameState(Box::new(game_state)),
 		..Default::default()
 	})?;
 
 	Ok(())
 }
 
-fn process_mark_player(
+fn process_register_organizer(
 	database: &DatabaseAccess,
-	clients: &ClientsMap,
+	clients: &mut ClientsMap,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
-	id: u8,
-	points: Option<usize>,
-	can_vote: Option<bool>,
+	username: String,
+	password: String,
 ) -> Result<()> {
-	debug!("===== Mark player");
+	debug!("===== Register organizer");
 
-	if !is_organizer(clients, &address) {
-		bail!("Mark player request came from a non-organizer");
+	if is_display(clients, &address) {
+		let message = "A display cannot log in as an organizer.".to_owned();
+		bail!(GameError::new(ErrorCode::Forbidden, message));
 	}
 
-	let updated_player = {
+	let account = {
 		let db_access = acquire_database_lock(database)?;
-		db_access.mark_player(id, points, can_vote)
+		db_access.find_organizer_by_username(&username)
 	}?;
 
-	announce_updated_player(clients, updated_player);
+	// Check if the credentials are valid
+	let organizer = match account {
+		Some(account) if verify_password(&password, &account.password_hash) => account.organizer,
+		_ => {
+			warn!(
+				"Failed organizer login attempt for {} from {}",
+				username, address
+			);
+			bail!(GameError::new(
+				ErrorCode::Forbidden,
+				"The organizer username or password is incorrect.",
+			));
+		}
+	};
+
+	// Update clients map
+	clients.entry(address).and_modify(|c| {
+		c.status = ClientStatus::Registered;
+		c.organizer = Some(organizer.clone());
+	});
+	info!(
+		"Organizer {} logged in from {}",
+		organizer.username, address
+	);
 
-	let ics = get_individual_channel_sender(&clients, &address)?;
+	let ics = get_individual_channel_sender(clients, &address)?;
 	ics.send(InternalMessage {
-		payload: InternalMessageAction::ResponseOkay,
+		payload: InternalMessageAction::ResponseOrganizerIdentity(organizer),
+		response_id,
+	})?;
+	debug!("Response (organizer identity confirmation) sent");
+
+	Ok(())
+}
+
+fn process_register_display(
+	database: &DatabaseAccess,
+	room: &Room,
+	clients: &mut ClientsMap,
+	timer: &Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Register display");
+
+	let is_logged_in = clients
+		.get(&address)
+		.is_some_and(|client| client.player.is_some() || client.organizer.is_some());
+	if is_logged_in {
+		let message = "This connection is already logged in as a player or organizer.".to_owned();
+		bail!(GameError::new(ErrorCode::Conflict, message));
+	}
+
+	// Displays only watch, so they never show up among the players
+	clients.entry(address).and_modify(|c| {
+		c.status = ClientStatus::Display;
+	});
+	info!("Display connected to room {} from {}", room.code, address);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseDisplayIdentity(room.clone()),
+		response_id,
+	})?;
+
+	let game_state = compile_game_state(database, room, clients, timer, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseGameState(Box::new(game_state)),
+		..Default::default()
+	})?;
+
+	Ok(())
+}
+
+fn process_exit_client(clients: &mut ClientsMap, address: SocketAddr) -> Result<()> {
+	debug!("===== Exit client");
+
+	clients.remove(&address);
+	debug!("Removed client: {}", address);
+
+	announce_active_players(clients);
+
+	Ok(())
+}
+
+fn process_retrieve_game_state(
+	database: &DatabaseAccess,
+	room: &Room,
+	clients: &ClientsMap,
+	timer: &Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Retrieve game state");
+
+	let game_state = compile_game_state(database, room, clients, timer, &address)?;
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseGameState(Box::new(game_state)),
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_set_round(
+	database: &DatabaseAccess,
+	config: &ConfigAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	round: Round,
+	override_transition: bool,
+) -> Result<()> {
+	debug!("===== Set round");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can set rounds."
+		));
+	};
+
+	let previous_state = {
+		let db_access = acquire_database_lock(database)?;
+		db_access
+			.find_round_by_number_and_phase(room_id, round.number, round.phase)?
+			.map(|db_round| db_round.state)
+	};
+
+	if let Err(error) = check_round_options(&round.options) {
+		bail!(GameError::new(ErrorCode::InvalidInput, error.to_string()));
+	}
+
+	if override_transition {
+		warn!(
+			"Organizer {} overrode the state checks for round {}-{}",
+			organizer.username, round.number, round.phase
+		);
+	} else if let Err(error) = check_round_transition(previous_state.as_ref(), &round.state) {
+		bail!(GameError::new(ErrorCode::InvalidState, error.to_string()));
+	}
+
+	let changed_by = format!("Organizer {}", organizer.username);
+	save_round(
+		database,
+		config,
+		room_id,
+		clients,
+		timer,
+		&changed_by,
+		round,
+		previous_state,
+	)?;
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_advance_round_state(
+	database: &DatabaseAccess,
+	config: &ConfigAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Advance round state");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can advance rounds."
+		));
+	};
+
+	let active_round = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.get_active_round(room_id)?
+	};
+
+	let next_round = match active_round {
+		None => Err("There is no round to advance yet.".to_owned()),
+		Some(round) => match round.state.next() {
+			None => Err(format!(
+				"Round {}-{} already shows its results. Set up the next round or phase instead.",
+				round.number, round.phase
+			)),
+			Some(next_state) => Ok((
+				round.state.clone(),
+				Round {
+					state: next_state,
+					..round
+				},
+			)),
+		},
+	};
+
+	let (previous_state, round) = match next_round {
+		Ok(next_round) => next_round,
+		Err(message) => bail!(GameError::new(ErrorCode::InvalidState, message)),
+	};
+
+	let changed_by = format!("Organizer {}", organizer.username);
+	save_round(
+		database,
+		config,
+		room_id,
+		clients,
+		timer,
+		&changed_by,
+		round,
+		Some(previous_state),
+	)?;
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+// Store a round that already passed the transition checks, then tell everyone about it
+#[allow(clippy::too_many_arguments)]
+fn save_round(
+	database: &DatabaseAccess,
+	config: &ConfigAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	changed_by: &str,
+	round: Round,
+	previous_state: Option<RoundState>,
+) -> Result<Round> {
+	let is_new_round = previous_state.is_none();
+
+	// The round, its result and the points it awards are stored together or not at all
+	let (round, result, scored_players, voting_players) = {
+		let db_access = acquire_database_lock(database)?;
+
+		transaction(&*db_access, |db_access| {
+			let round = if is_new_round {
+				db_access.create_round(
+					room_id,
+					round.number,
+					round.phase,
+					round.state,
+					round.question,
+					round.options,
+				)?
+			} else {
+				db_access.update_round(
+					room_id,
+					round.number,
+					round.phase,
+					Some(round.state),
+					Some(round.question),
+					Some(round.options),
+				)?
+			};
+
+			// Resolve the round when it enters the results state
+			let mut result = None;
+			let mut scored_players = vec![];
+			if round.state == RoundState::ShowResults
+				&& previous_state != Some(RoundState::ShowResults)
+			{
+				let choices = db_access.get_choices_by_round_id(round.id)?;
+				let round_result = compute_round_result(&round, &choices);
+				// Rounds brought back to their results by an override were already scored
+				if db_access.mark_round_resolved(round.id)? {
+					scored_players = apply_round_result(db_access, config, room_id, &round_result)?;
+				}
+				result = Some(round_result);
+			}
+
+			// Set can_vote to true to all active players if it's the standby state of a new round
+			let mut voting_players = vec![];
+			if round.phase == 1 && round.state == RoundState::Standby {
+				voting_players = allow_all_active_players_to_vote(db_access, room_id, clients)?;
+			}
+
+			Ok((round, result, scored_players, voting_players))
+		})?
+	};
+
+	info!(
+		"{} set round {}-{} to {}",
+		changed_by,
+		round.number,
+		round.phase,
+		round.state.as_str()
+	);
+
+	// A timer only counts down the state it was started in
+	let timer_is_stale = timer
+		.as_ref()
+		.is_some_and(|timer| timer.round_id != round.id || timer.state != round.state);
+	if timer_is_stale {
+		*timer = None;
+		announce_timer(clients, timer);
+	}
+
+	// Announce updated round
+	announce_round(database, room_id, clients, Some(round.clone()));
+
+	// Announce choices if the round is new or in a state where choices are being shown
+	if is_new_round
+		|| round.state == RoundState::ShowVotes
+		|| round.state == RoundState::VotingTime
+		|| round.state == RoundState::VotingLocked
+		|| round.state == RoundState::ShowResults
+	{
+		let updated_choices = compile_choices(database, &round)?;
+		announce_updated_choices(clients, &round, updated_choices);
+	}
+
+	if let Some(result) = result {
+		for player in scored_players {
+			announce_updated_player(clients, player);
+		}
+
+		info!(
+			"Round {}-{} resolved: {} winners, {} losers",
+			round.number,
+			round.phase,
+			result.winners.len(),
+			result.losers.len()
+		);
+		announce_round_result(clients, result);
+	}
+
+	for player in voting_players {
+		announce_updated_player(clients, player);
+	}
+
+	// Rounds from the game plan can come with their own voting time
+	if round.state == RoundState::VotingTime
+		&& previous_state != Some(RoundState::VotingTime)
+		&& timer.is_none()
+	{
+		let planned_round = {
+			let db_access = acquire_database_lock(database)?;
+			db_access.find_planned_round(room_id, round.number, round.phase)?
+		};
+
+		if let Some(seconds) = planned_round.and_then(|planned| planned.voting_seconds) {
+			info!(
+				"Starting the planned {} second voting timer for round {}-{}",
+				seconds, round.number, round.phase
+			);
+			*timer = Some(RoundTimer::start(
+				round.id,
+				RoundState::VotingTime,
+				RoundState::VotingLocked,
+				Duration::from_secs(seconds),
+			));
+			announce_timer(clients, timer);
+		}
+	}
+
+	Ok(round)
+}
+
+fn process_load_plan(
+	database: &DatabaseAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	format: PlanFormat,
+	content: &str,
+) -> Result<()> {
+	debug!("===== Load plan");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can load game plans."
+		));
+	};
+
+	let plan = match parse_game_plan(format, content) {
+		Ok(plan) => plan,
+		Err(error) => {
+			let message = format!("Could not read the game plan: {:#}", error);
+			bail!(GameError::new(ErrorCode::InvalidInput, message));
+		}
+	};
+
+	{
+		let db_access = acquire_database_lock(database)?;
+		db_access.replace_game_plan(room_id, &plan)?;
+	}
+	info!(
+		"Organizer {} loaded a game plan with {} rounds",
+		organizer.username,
+		plan.len()
+	);
+
+	announce_game_plan_to_organizers(clients, plan);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_retrieve_game_plan(
+	database: &DatabaseAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Retrieve game plan");
+
+	if get_organizer(clients, &address).is_none() {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can see the game plan."
+		));
+	}
+
+	let plan = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.get_game_plan(room_id)?
+	};
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseGamePlan(plan),
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+// Create the round that follows the active one in the game plan
+fn process_next_round(
+	database: &DatabaseAccess,
+	config: &ConfigAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Next round");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can start the next round."
+		));
+	};
+
+	let (active_round, plan) = {
+		let db_access = acquire_database_lock(database)?;
+		(
+			db_access.get_active_round(room_id)?,
+			db_access.get_game_plan(room_id)?,
+		)
+	};
+
+	if plan.is_empty() {
+		let message = "There is no game plan loaded.".to_owned();
+		bail!(GameError::new(ErrorCode::InvalidState, message));
+	}
+
+	if let Some(round) = &active_round {
+		if round.state != RoundState::ShowResults {
+			let message = format!(
+				"Round {}-{} is still at {}. Show its results before moving on.",
+				round.number,
+				round.phase,
+				round.state.as_str()
+			);
+			bail!(GameError::new(ErrorCode::InvalidState, message));
+		}
+	}
+
+	let played = active_round
+		.as_ref()
+		.map(|round| (round.number, round.phase));
+	let Some(planned_round) = plan
+		.into_iter()
+		.find(|planned| Some((planned.number, planned.phase)) > played)
+	else {
+		let message = "The game plan has no more rounds.".to_owned();
+		bail!(GameError::new(ErrorCode::InvalidState, message));
+	};
+
+	let changed_by = format!("Organizer {}", organizer.username);
+	save_round(
+		database,
+		config,
+		room_id,
+		clients,
+		timer,
+		&changed_by,
+		Round {
+			id: 0,
+			number: planned_round.number,
+			phase: planned_round.phase,
+			state: RoundState::Standby,
+			question: planned_round.question,
+			options: planned_round.options,
+		},
+		None,
+	)?;
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+// Wake up call for the running timer: tick, or move the round on once it runs out
+fn process_timer(
+	database: &DatabaseAccess,
+	config: &ConfigAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+) -> Result<()> {
+	debug!("===== Timer");
+
+	let Some(running_timer) = timer.as_mut() else {
+		return Ok(());
+	};
+
+	if !running_timer.is_expired() {
+		running_timer.schedule_next_tick();
+		announce_timer(clients, timer);
+		return Ok(());
+	}
+
+	let expired_timer = timer.take().expect("The timer was checked above");
+	announce_timer(clients, timer);
+
+	let Some(mut round) = ({
+		let db_access = acquire_database_lock(database)?;
+		db_access.get_active_round(room_id)?
+	}) else {
+		return Ok(());
+	};
+
+	// The organizers moved on by hand in the meantime
+	if round.id != expired_timer.round_id || round.state != expired_timer.state {
+		return Ok(());
+	}
+
+	info!(
+		"Timer for round {}-{} ran out, moving on to {}",
+		round.number,
+		round.phase,
+		expired_timer.advance_to.as_str()
+	);
+
+	// Step through every state in between so each one has its usual effects
+	while round.state != expired_timer.advance_to {
+		let Some(next_state) = round.state.next() else {
+			break;
+		};
+
+		let previous_state = round.state.clone();
+		round = save_round(
+			database,
+			config,
+			room_id,
+			clients,
+			timer,
+			"Timer",
+			Round {
+				state: next_state,
+				..round
+			},
+			Some(previous_state),
+		)?;
+	}
+
+	Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_start_timer(
+	database: &DatabaseAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	seconds: u64,
+	advance_to: Option<RoundState>,
+) -> Result<()> {
+	debug!("===== Start timer");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can start timers."
+		));
+	};
+
+	let active_round = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.get_active_round(room_id)?
+	};
+	let Some(round) = active_round else {
+		let message = "There is no round to time yet.".to_owned();
+		bail!(GameError::new(ErrorCode::InvalidState, message));
+	};
+
+	if seconds == 0 {
+		let message = "A timer has to run for at least one second.".to_owned();
+		bail!(GameError::new(ErrorCode::InvalidInput, message));
+	}
+	if seconds > MAX_DURATION.as_secs() {
+		let message = format!(
+			"A timer can run for at most {} seconds.",
+			MAX_DURATION.as_secs()
+		);
+		bail!(GameError::new(ErrorCode::InvalidInput, message));
+	}
+
+	let Some(next_state) = round.state.next() else {
+		let message = format!(
+			"Round {}-{} already shows its results, there is nothing to time.",
+			round.number, round.phase
+		);
+		bail!(GameError::new(ErrorCode::InvalidState, message));
+	};
+
+	// The timer can only move the round forwards
+	let advance_to = advance_to.unwrap_or_else(|| next_state.clone());
+	let mut later_state = Some(next_state);
+	while later_state
+		.as_ref()
+		.is_some_and(|state| *state != advance_to)
+	{
+		later_state = later_state.and_then(|state| state.next());
+	}
+	if later_state.is_none() {
+		let message = format!(
+			"The timer can only move round {}-{} forwards from {}, not to {}.",
+			round.number,
+			round.phase,
+			round.state.as_str(),
+			advance_to.as_str()
+		);
+		bail!(GameError::new(ErrorCode::InvalidInput, message));
+	}
+
+	info!(
+		"Organizer {} started a {} second timer for round {}-{} ({} to {})",
+		organizer.username,
+		seconds,
+		round.number,
+		round.phase,
+		round.state.as_str(),
+		advance_to.as_str()
+	);
+
+	*timer = Some(RoundTimer::start(
+		round.id,
+		round.state,
+		advance_to,
+		Duration::from_secs(seconds),
+	));
+	announce_timer(clients, timer);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_pause_timer(
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Pause timer");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can pause timers."
+		));
+	};
+
+	match timer.as_mut() {
+		Some(running_timer) if running_timer.is_running() => running_timer.pause(),
+		Some(_) => {
+			let message = "The timer is already paused.".to_owned();
+			bail!(GameError::new(ErrorCode::InvalidState, message));
+		}
+		None => {
+			let message = "There is no timer to pause.".to_owned();
+			bail!(GameError::new(ErrorCode::InvalidState, message));
+		}
+	}
+	info!("Organizer {} paused the timer", organizer.username);
+
+	announce_timer(clients, timer);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_resume_timer(
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Resume timer");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can resume timers."
+		));
+	};
+
+	match timer.as_mut() {
+		Some(paused_timer) if !paused_timer.is_running() => paused_timer.resume(),
+		Some(_) => {
+			let message = "The timer is already running.".to_owned();
+			bail!(GameError::new(ErrorCode::InvalidState, message));
+		}
+		None => {
+			let message = "There is no timer to resume.".to_owned();
+			bail!(GameError::new(ErrorCode::InvalidState, message));
+		}
+	}
+	info!("Organizer {} resumed the timer", organizer.username);
+
+	announce_timer(clients, timer);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_extend_timer(
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	seconds: u64,
+) -> Result<()> {
+	debug!("===== Extend timer");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can extend timers."
+		));
+	};
+
+	let Some(existing_timer) = timer.as_mut() else {
+		let message = "There is no timer to extend.".to_owned();
+		bail!(GameError::new(ErrorCode::InvalidState, message));
+	};
+	let remaining = existing_timer.remaining().as_secs();
+	if seconds > MAX_DURATION.as_secs().saturating_sub(remaining) {
+		let message = format!(
+			"A timer can run for at most {} seconds.",
+			MAX_DURATION.as_secs()
+		);
+		bail!(GameError::new(ErrorCode::InvalidInput, message));
+	}
+	existing_timer.extend(Duration::from_secs(seconds));
+	info!(
+		"Organizer {} extended the timer by {} seconds",
+		organizer.username, seconds
+	);
+
+	announce_timer(clients, timer);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_cancel_timer(
+	clients: &ClientsMap,
+	timer: &mut Option<RoundTimer>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) -> Result<()> {
+	debug!("===== Cancel timer");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can cancel timers."
+		));
+	};
+
+	if timer.take().is_none() {
+		let message = "There is no timer to cancel.".to_owned();
+		bail!(GameError::new(ErrorCode::InvalidState, message));
+	}
+	info!("Organizer {} cancelled the timer", organizer.username);
+
+	announce_timer(clients, timer);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_set_choice(
+	database: &DatabaseAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	option: ChoiceOption,
+) -> Result<()> {
+	debug!("===== Set choice");
+
+	if !is_player(clients, &address) {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only players can vote."
+		));
+	}
+
+	let round = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.get_active_round(room_id)?
+	};
+	if round.is_none() {
+		bail!(GameError::new(
+			ErrorCode::InvalidState,
+			"There is no round to vote on yet.",
+		));
+	}
+	let round = round.unwrap();
+
+	// Votes can't be cast or changed once the tally may have been seen
+	if round.state != RoundState::VotingTime {
+		let message = format!(
+			"Voting is closed. Votes are only accepted during {}, but the round is at {}.",
+			RoundState::VotingTime.as_str(),
+			round.state.as_str()
+		);
+		bail!(GameError::new(ErrorCode::InvalidState, message));
+	}
+
+	if option.index() >= round.options.len() {
+		let message = format!(
+			"Round {}-{} has no option {}.",
+			round.number,
+			round.phase,
+			option.letter().to_ascii_uppercase()
+		);
+		bail!(GameError::new(ErrorCode::InvalidInput, message));
+	}
+
+	let client = clients.get(&address);
+	if client.is_none() {
+		bail!("Could not find the client");
+	}
+	let player = client.unwrap().clone().player;
+	if player.is_none() {
+		bail!("The client is not a player");
+	}
+	let player = player.unwrap();
+
+	let is_player_allowed_to_vote = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.check_player_is_allowed_to_vote(player.id)?
+	};
+
+	if !is_player_allowed_to_vote {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"You are not allowed to vote.",
+		));
+	}
+
+	let set_choice = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.update_or_create_choice(round.id, player.id, option)
+	}?;
+
+	announce_choice_to_organizers(clients, player, set_choice);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_set_late_vote(
+	database: &DatabaseAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	player_id: i64,
+	option: ChoiceOption,
+) -> Result<()> {
+	debug!("===== Set late vote");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can record late votes."
+		));
+	};
+
+	let round = get_active_round(database, room_id)?;
+	check_choices_open(database, &round)?;
+
+	if option.index() >= round.options.len() {
+		let message = format!(
+			"Round {}-{} has no option {}.",
+			round.number,
+			round.phase,
+			option.letter().to_ascii_uppercase()
+		);
+		bail!(GameError::new(ErrorCode::InvalidInput, message));
+	}
+
+	let player = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.find_player_by_id(room_id, player_id)?
+	};
+	let Some(player) = player else {
+		let message = format!("There is no player with id {}.", player_id);
+		bail!(GameError::new(ErrorCode::NotFound, message));
+	};
+
+	let set_choice = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.update_or_create_choice(round.id, player.id, option)
+	}?;
+
+	info!(
+		"Organizer {} recorded a late vote for {} in round {}-{} at {}",
+		organizer.username,
+		player.name,
+		round.number,
+		round.phase,
+		round.state.as_str()
+	);
+
+	announce_choice_to_organizers(clients, player, set_choice);
+
+	let updated_choices = compile_choices(database, &round)?;
+	announce_updated_choices(clients, &round, updated_choices);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_retrieve_vote_history(
+	database: &DatabaseAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	round_id: i64,
+	player_id: i64,
+) -> Result<()> {
+	debug!("===== Retrieve vote history");
+
+	if get_organizer(clients, &address).is_none() {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can see the vote history."
+		));
+	}
+
+	// Neither the round nor the player may come from another room
+	let events = {
+		let db_access = acquire_database_lock(database)?;
+		if db_access.find_round_in_room(room_id, round_id)?.is_none() {
+			let message = format!("There is no round with id {}.", round_id);
+			bail!(GameError::new(ErrorCode::NotFound, message));
+		}
+		if db_access.find_player_by_id(room_id, player_id)?.is_none() {
+			let message = format!("There is no player with id {}.", player_id);
+			bail!(GameError::new(ErrorCode::NotFound, message));
+		}
+
+		db_access.get_choice_events(round_id, player_id)?
+	};
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseVoteHistory(round_id, player_id, events),
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+fn process_mark_choice(
+	database: &DatabaseAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	id: i64,
+	lie: Option<bool>,
+) -> Result<()> {
+	debug!("===== Mark choice");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can mark choices."
+		));
+	};
+
+	let round = get_active_round(database, room_id)?;
+	check_choices_open(database, &round)?;
+
+	// Only the choices of this room's round can be marked from here
+	let choices = compile_choices(database, &round)?;
+	if !choices.values().any(|choice| choice.id == id) {
+		let message = format!(
+			"Round {}-{} has no choice with id {}.",
+			round.number, round.phase, id
+		);
+		bail!(GameError::new(ErrorCode::NotFound, message));
+	}
+
+	{
+		let db_access = acquire_database_lock(database)?;
+		db_access.mark_choice(id, lie)
+	}?;
+	info!(
+		"Organizer {} marked choice {} (lie: {:?})",
+		organizer.username, id, lie
+	);
+
+	let updated_choices = compile_choices(database, &round)?;
+	announce_updated_choices(clients, &round, updated_choices);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
+		response_id,
+	})?;
+
+	Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_mark_player(
+	database: &DatabaseAccess,
+	room_id: i64,
+	clients: &ClientsMap,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	id: i64,
+	points: Option<usize>,
+	can_vote: Option<bool>,
+) -> Result<()> {
+	debug!("===== Mark player");
+
+	let Some(organizer) = get_organizer(clients, &address) else {
+		bail!(GameError::new(
+			ErrorCode::Forbidden,
+			"Only organizers can change players."
+		));
+	};
+
+	let updated_player = {
+		let db_access = acquire_database_lock(database)?;
+		db_access.mark_player(room_id, id, points, can_vote)
+	}?;
+	info!(
+		"Organizer {} marked player {} (points: {:?}, can vote: {:?})",
+		organizer.username, updated_player.name, points, can_vote
+	);
+
+	announce_updated_player(clients, updated_player);
+
+	let ics = get_individual_channel_sender(clients, &address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseOkay,
 		response_id,
-		..Default::default()
 	})?;
 
 	Ok(())
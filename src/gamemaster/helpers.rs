@@ -1,19 +1,34 @@
-use std::{collections::HashMap, net::SocketAddr, sync::MutexGuard};
+use std::{
+	collections::{hash_map::Entry, HashMap},
+	net::SocketAddr,
+	sync::MutexGuard,
+	time::{Duration, Instant},
+};
 
-use anyhow::{bail, Result};
-use crossbeam::channel::Sender;
-use log::{debug, warn};
+use anyhow::{anyhow, bail, Result};
+use log::{debug, error, info, warn};
+use uuid::Uuid;
 
 use crate::{
+	config::config::ConfigAccess,
 	database::database::{Database, DatabaseAccess},
-	postmaster::types::{InternalMessage, InternalMessageAction},
+	postmaster::types::{InternalMessage, InternalMessageAction, ResponseIdentifier},
 };
 
-use super::types::{Choice, ChoicesMap, Client, ClientsMap, GameState, Player, Round};
+use super::{
+	error::{ErrorCode, GameError},
+	outbox::ClientOutbox,
+	timer::RoundTimer,
+	types::{
+		Choice, ChoiceOption, ChoicesMap, Client, ClientStatus, ClientsMap, Game, GameRoom,
+		GameState, Organizer, PlannedRound, Player, Room, RoomsMap, Round, RoundResult, RoundState,
+		Session, SessionsMap, MAX_ROUND_OPTIONS, MIN_ROUND_OPTIONS,
+	},
+};
 
 pub(super) fn acquire_database_lock(
 	database_access: &DatabaseAccess,
-) -> Result<MutexGuard<'_, (dyn Database + 'static)>> {
+) -> Result<MutexGuard<'_, dyn Database + 'static>> {
 	let db_lock = database_access.try_lock();
 	if db_lock.is_err() {
 		bail!("Could not get access to database");
@@ -25,7 +40,7 @@ pub(super) fn acquire_database_lock(
 pub(super) fn get_individual_channel_sender<'map_lifetime>(
 	clients_map: &'map_lifetime ClientsMap,
 	address: &SocketAddr,
-) -> Result<&'map_lifetime Sender<InternalMessage>> {
+) -> Result<&'map_lifetime ClientOutbox> {
 	debug!("===== Get ICS");
 
 	let client = clients_map.get(address);
@@ -37,12 +52,150 @@ pub(super) fn get_individual_channel_sender<'map_lifetime>(
 	Ok(&client.individual_channel_sender)
 }
 
+pub(super) fn issue_session_token(
+	config: &ConfigAccess,
+	sessions: &mut SessionsMap,
+	room_id: i64,
+	player_id: i64,
+) -> String {
+	debug!("===== Issue session token");
+
+	// A player only ever holds one valid token, and expired ones are of no use to anybody
+	let now = Instant::now();
+	sessions.retain(|_, session| session.player_id != player_id && session.expires_at > now);
+
+	let session_token = Uuid::new_v4().to_string();
+	sessions.insert(
+		session_token.clone(),
+		Session {
+			room_id,
+			player_id,
+			expires_at: now + Duration::from_secs(config.session_lifetime),
+		},
+	);
+
+	session_token
+}
+
+// Tokens from the previous game stop working, the players still connected get new ones
+pub(super) fn reissue_session_tokens(
+	config: &ConfigAccess,
+	sessions: &mut SessionsMap,
+	room_id: i64,
+	clients: &ClientsMap,
+) {
+	debug!("===== Reissue session tokens");
+
+	sessions.retain(|_, session| session.room_id != room_id);
+
+	for client in clients.values() {
+		let Some(player) = client.player.as_ref() else {
+			continue;
+		};
+
+		let session_token = issue_session_token(config, sessions, room_id, player.id);
+		let _ = client.individual_channel_sender.send(InternalMessage {
+			payload: InternalMessageAction::ResponsePlayerIdentity(player.clone(), session_token),
+			..Default::default()
+		});
+	}
+}
+
+pub(super) fn find_client_room_id(rooms: &RoomsMap, address: &SocketAddr) -> Option<i64> {
+	rooms
+		.iter()
+		.find(|(_, game_room)| game_room.clients.contains_key(address))
+		.map(|(room_id, _)| *room_id)
+}
+
+pub(super) fn get_client_room<'map_lifetime>(
+	rooms: &'map_lifetime mut RoomsMap,
+	address: &SocketAddr,
+) -> Result<&'map_lifetime mut GameRoom> {
+	debug!("===== Get client room");
+
+	let Some(room_id) = find_client_room_id(rooms, address) else {
+		bail!("Could not find the room of client {}", address);
+	};
+
+	rooms
+		.get_mut(&room_id)
+		.ok_or(anyhow!("Could not get room {}", room_id))
+}
+
+pub(super) fn load_room<'map_lifetime>(
+	database: &DatabaseAccess,
+	rooms: &'map_lifetime mut RoomsMap,
+	room_id: i64,
+) -> Result<&'map_lifetime mut GameRoom> {
+	debug!("===== Load room");
+
+	match rooms.entry(room_id) {
+		Entry::Occupied(entry) => Ok(entry.into_mut()),
+		Entry::Vacant(entry) => {
+			let room = {
+				let db_access = acquire_database_lock(database)?;
+				db_access.find_room_by_id(room_id)?
+			}
+			.ok_or(anyhow!("Could not find room {}", room_id))?;
+
+			Ok(entry.insert(GameRoom {
+				room,
+				clients: HashMap::new(),
+				timer: None,
+			}))
+		}
+	}
+}
+
+pub(super) fn move_client(
+	database: &DatabaseAccess,
+	rooms: &mut RoomsMap,
+	address: SocketAddr,
+	room_id: i64,
+) -> Result<()> {
+	debug!("===== Move client");
+
+	let Some(current_room_id) = find_client_room_id(rooms, &address) else {
+		bail!("Could not find the room of client {}", address);
+	};
+	if current_room_id == room_id {
+		return Ok(());
+	}
+
+	// Load the new room first so a failure leaves the client where it was
+	load_room(database, rooms, room_id)?;
+
+	let client = rooms
+		.get_mut(&current_room_id)
+		.and_then(|game_room| game_room.clients.remove(&address))
+		.ok_or(anyhow!("Could not take client {} out of its room", address))?;
+	load_room(database, rooms, room_id)?
+		.clients
+		.insert(address, client);
+
+	Ok(())
+}
+
+// Join codes are short enough to read out loud, without look-alike characters
+const ROOM_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const ROOM_CODE_LENGTH: usize = 6;
+
+pub(super) fn generate_room_code() -> String {
+	Uuid::new_v4()
+		.as_bytes()
+		.iter()
+		.take(ROOM_CODE_LENGTH)
+		.map(|byte| char::from(ROOM_CODE_ALPHABET[usize::from(*byte) % ROOM_CODE_ALPHABET.len()]))
+		.collect()
+}
+
 pub(super) fn get_players(clients_map: &ClientsMap) -> Vec<(&SocketAddr, &Player)> {
 	debug!("===== Get Players");
 
 	let mut existing_ids = vec![];
 	let mut players = vec![];
-	for (address, client) in clients_map.into_iter() {
+	for (address, client) in clients_map.iter() {
 		if client.player.is_none() {
 			continue;
 		}
@@ -59,7 +212,7 @@ pub(super) fn get_players(clients_map: &ClientsMap) -> Vec<(&SocketAddr, &Player
 	players
 }
 
-pub(super) fn get_cloned_map_of_players(clients_map: &ClientsMap) -> HashMap<u8, Player> {
+pub(super) fn get_cloned_map_of_players(clients_map: &ClientsMap) -> HashMap<i64, Player> {
 	debug!("===== Get cloned list of Players");
 
 	let mut map = HashMap::new();
@@ -72,6 +225,25 @@ pub(super) fn get_cloned_map_of_players(clients_map: &ClientsMap) -> HashMap<u8,
 	map
 }
 
+// Refreshes the players kept with their clients, e.g. their points after a new game started
+pub(super) fn reload_players(
+	db_access: &dyn Database,
+	room_id: i64,
+	clients: &mut ClientsMap,
+) -> Result<()> {
+	debug!("===== Reload players");
+
+	for client in clients.values_mut() {
+		let Some(player) = client.player.as_ref() else {
+			continue;
+		};
+
+		client.player = db_access.find_player_by_id(room_id, player.id)?;
+	}
+
+	Ok(())
+}
+
 pub(super) fn is_player(clients: &ClientsMap, address: &SocketAddr) -> bool {
 	debug!("===== Is player?");
 
@@ -81,7 +253,15 @@ pub(super) fn is_player(clients: &ClientsMap, address: &SocketAddr) -> bool {
 	}
 	let client = client.unwrap();
 
-	return client.player.is_some();
+	client.player.is_some()
+}
+
+pub(super) fn is_display(clients: &ClientsMap, address: &SocketAddr) -> bool {
+	debug!("===== Is display?");
+
+	clients
+		.get(address)
+		.is_some_and(|client| matches!(client.status, ClientStatus::Display))
 }
 
 pub(super) fn get_organizers(clients_map: &ClientsMap) -> Vec<(&SocketAddr, &Client)> {
@@ -89,7 +269,7 @@ pub(super) fn get_organizers(clients_map: &ClientsMap) -> Vec<(&SocketAddr, &Cli
 
 	let mut organizers = vec![];
 
-	for (address, client) in clients_map.into_iter() {
+	for (address, client) in clients_map.iter() {
 		if client.organizer.is_none() {
 			continue;
 		}
@@ -100,32 +280,46 @@ pub(super) fn get_organizers(clients_map: &ClientsMap) -> Vec<(&SocketAddr, &Cli
 	organizers
 }
 
-pub(super) fn is_organizer(clients: &ClientsMap, address: &SocketAddr) -> bool {
-	debug!("===== Is organizer?");
-
-	let client = clients.get(address);
-	if client.is_none() {
-		return false;
-	}
-	let client = client.unwrap();
+pub(super) fn get_organizer<'map_lifetime>(
+	clients: &'map_lifetime ClientsMap,
+	address: &SocketAddr,
+) -> Option<&'map_lifetime Organizer> {
+	debug!("===== Get organizer");
 
-	return client.organizer.is_some();
+	clients.get(address)?.organizer.as_ref()
 }
 
-pub(super) fn get_active_round(database: &DatabaseAccess) -> Result<Round> {
+pub(super) fn get_active_round(database: &DatabaseAccess, room_id: i64) -> Result<Round> {
 	debug!("===== Get active round");
 
 	// Acquire lock
 	let db_access = acquire_database_lock(database)?;
-	let active_round = db_access.get_active_round()?;
+	let active_round = db_access.get_active_round(room_id)?;
 
 	if active_round.is_none() {
-		bail!("There is no active round");
+		bail!(GameError::new(
+			ErrorCode::InvalidState,
+			"There is no active round.",
+		));
 	}
 
 	Ok(active_round.unwrap())
 }
 
+// The votes of a resolved round are final, see apply_round_result
+pub(super) fn check_choices_open(database: &DatabaseAccess, round: &Round) -> Result<()> {
+	let db_access = acquire_database_lock(database)?;
+	if db_access.is_round_resolved(round.id)? {
+		let message = format!(
+			"Round {}-{} was already scored, its votes can't change anymore.",
+			round.number, round.phase
+		);
+		bail!(GameError::new(ErrorCode::InvalidState, message));
+	}
+
+	Ok(())
+}
+
 pub(super) fn compile_choices(database: &DatabaseAccess, round: &Round) -> Result<ChoicesMap> {
 	debug!("===== Compile choices");
 
@@ -133,46 +327,314 @@ pub(super) fn compile_choices(database: &DatabaseAccess, round: &Round) -> Resul
 
 	// Acquire lock
 	let db_access = acquire_database_lock(database)?;
-	match db_access.get_choices_by_round_id(round.id) {
-		Ok(choices_for_active_round) => {
-			choices = choices_for_active_round;
-		}
-		Err(_) => {}
+	if let Ok(choices_for_active_round) = db_access.get_choices_by_round_id(round.id) {
+		choices = choices_for_active_round;
 	}
 
 	Ok(choices)
 }
 
+// The game state as the client at the address may see it
 pub(super) fn compile_game_state(
 	database: &DatabaseAccess,
+	room: &Room,
 	clients: &ClientsMap,
+	timer: &Option<RoundTimer>,
+	address: &SocketAddr,
 ) -> Result<GameState> {
 	debug!("===== Compile game state");
 
+	let Some(client) = clients.get(address) else {
+		bail!("Could not get client from map with address {}", address);
+	};
+
+	let game_state = compile_full_game_state(database, room, clients, timer)?;
+
+	Ok(filter_game_state(client, game_state))
+}
+
+fn compile_full_game_state(
+	database: &DatabaseAccess,
+	room: &Room,
+	clients: &ClientsMap,
+	timer: &Option<RoundTimer>,
+) -> Result<GameState> {
+	debug!("===== Compile full game state");
+
 	let players = get_cloned_map_of_players(clients);
 
 	let mut choices: ChoicesMap = HashMap::new();
 
-	let round = {
+	let (game, round) = {
 		let db_access = acquire_database_lock(database)?;
-		db_access.get_active_round()?
+		(
+			db_access.get_current_game(room.id)?,
+			db_access.get_active_round(room.id)?,
+		)
 	};
 
-	if round.is_some() {
-		choices = compile_choices(database, round.as_ref().unwrap())?;
+	let mut result = None;
+
+	if let Some(round) = round.as_ref() {
+		choices = compile_choices(database, round)?;
+
+		if round.state == RoundState::ShowResults {
+			result = Some(compute_round_result(round, &choices));
+		}
 	}
 
 	Ok(GameState {
+		room: room.clone(),
+		game,
 		round,
 		players,
 		choices,
+		result,
+		timer: timer.as_ref().map(RoundTimer::status),
 	})
 }
 
+fn filter_game_state(client: &Client, game_state: GameState) -> GameState {
+	let choices = filter_choices(client, game_state.round.as_ref(), &game_state.choices);
+
+	GameState {
+		choices,
+		..game_state
+	}
+}
+
+// Who voted for what stays hidden until the votes are shown, or the minority could be gamed
+fn filter_choices(client: &Client, round: Option<&Round>, choices: &ChoicesMap) -> ChoicesMap {
+	let are_votes_shown = round.is_some_and(|round| {
+		matches!(
+			round.state,
+			RoundState::ShowVotes | RoundState::Defense | RoundState::ShowResults
+		)
+	});
+	if client.organizer.is_some() || are_votes_shown {
+		return choices.clone();
+	}
+
+	// Players still see their own vote, displays see nothing yet
+	let Some(player) = client.player.as_ref() else {
+		return HashMap::new();
+	};
+
+	choices
+		.iter()
+		.filter(|(player_id, _)| **player_id == player.id)
+		.map(|(player_id, choice)| (*player_id, choice.clone()))
+		.collect()
+}
+
+pub(super) fn check_round_transition(
+	current: Option<&RoundState>,
+	next: &RoundState,
+) -> Result<()> {
+	debug!("===== Check round transition");
+
+	let Some(current) = current else {
+		// New rounds and phases start from the beginning
+		if *next != RoundState::Standby {
+			bail!(
+				"A new round must start at {}, not {}.",
+				RoundState::Standby.as_str(),
+				next.as_str()
+			);
+		}
+
+		return Ok(());
+	};
+
+	// Staying in the same state only updates the question and choices
+	if current == next || current.next().as_ref() == Some(next) {
+		return Ok(());
+	}
+
+	match current.next() {
+		Some(expected) => bail!(
+			"The round can only go from {} to {}, not {}. Use override to correct it.",
+			current.as_str(),
+			expected.as_str(),
+			next.as_str()
+		),
+		None => bail!(
+			"The round already shows its results and can't go back to {}. Use override to correct it.",
+			next.as_str()
+		),
+	}
+}
+
+pub(super) fn check_round_options(options: &[String]) -> Result<()> {
+	if options.len() < MIN_ROUND_OPTIONS || options.len() > MAX_ROUND_OPTIONS {
+		bail!(
+			"A round needs between {} and {} options, not {}.",
+			MIN_ROUND_OPTIONS,
+			MAX_ROUND_OPTIONS,
+			options.len()
+		);
+	}
+	if options.iter().any(|option| option.trim().is_empty()) {
+		bail!("Options can't be empty.");
+	}
+
+	Ok(())
+}
+
+pub(super) fn compute_round_result(round: &Round, choices: &ChoicesMap) -> RoundResult {
+	debug!("===== Compute round result");
+
+	// Choices marked as lies don't count towards any option
+	let mut voters: Vec<Vec<i64>> = vec![vec![]; round.options.len()];
+	for (player_id, choice) in choices {
+		if choice.lie {
+			continue;
+		}
+
+		if let Some(option_voters) = voters.get_mut(choice.option.index()) {
+			option_voters.push(*player_id);
+		}
+	}
+	for option_voters in voters.iter_mut() {
+		option_voters.sort();
+	}
+
+	let votes: Vec<usize> = voters.iter().map(Vec::len).collect();
+
+	// The option with the fewest votes wins, as long as it got any.
+	// It's a draw when that is shared or only one option was picked.
+	let picked: Vec<usize> = votes.iter().copied().filter(|count| *count > 0).collect();
+	let minority = picked.iter().min().and_then(|fewest| {
+		let shared = picked.iter().filter(|count| *count == fewest).count() > 1;
+		if picked.len() < 2 || shared {
+			return None;
+		}
+
+		votes
+			.iter()
+			.position(|count| count == fewest)
+			.and_then(|position| u8::try_from(position).ok())
+			.map(ChoiceOption)
+	});
+
+	let (winners, losers) = match minority {
+		None => (vec![], vec![]),
+		Some(minority) => {
+			let mut losers = vec![];
+			let mut winners = vec![];
+			for (position, option_voters) in voters.into_iter().enumerate() {
+				if position == minority.index() {
+					winners = option_voters;
+				} else {
+					losers.extend(option_voters);
+				}
+			}
+			losers.sort();
+
+			(winners, losers)
+		}
+	};
+
+	RoundResult {
+		round_id: round.id,
+		minority,
+		votes,
+		winners,
+		losers,
+	}
+}
+
+// Returns the players it changed, to be announced once the transaction is committed.
+// A round is only scored once, so its choices are frozen from then on: lies and late
+// votes are refused, even after an override takes the round back before its results.
+// Only counted votes decide it. Players who didn't vote, or whose vote was a lie, are
+// neither winners nor losers; the organizer can still take away their vote by hand.
+pub(super) fn apply_round_result(
+	db_access: &dyn Database,
+	config: &ConfigAccess,
+	room_id: i64,
+	result: &RoundResult,
+) -> Result<Vec<Player>> {
+	debug!("===== Apply round result");
+
+	let mut updated_players = vec![];
+
+	for player_id in &result.winners {
+		let Some(player) = db_access.find_player_by_id(room_id, *player_id)? else {
+			continue;
+		};
+
+		let points = player.points.unwrap_or(0) + config.points_per_win;
+		updated_players.push(db_access.mark_player(room_id, player.id, Some(points), None)?);
+	}
+
+	for player_id in &result.losers {
+		updated_players.push(db_access.mark_player(room_id, *player_id, None, Some(false))?);
+	}
+
+	Ok(updated_players)
+}
+
+pub(super) fn disconnect_lagging_clients(config: &ConfigAccess, rooms: &mut RoomsMap) {
+	for game_room in rooms.values_mut() {
+		disconnect_lagging_room_clients(config, &mut game_room.clients);
+	}
+}
+
+fn disconnect_lagging_room_clients(config: &ConfigAccess, clients: &mut ClientsMap) {
+	let lagging_addresses: Vec<SocketAddr> = clients
+		.iter()
+		.filter(|(_, client)| {
+			client
+				.individual_channel_sender
+				.is_lagging(config.slow_client_policy, config.slow_client_lag_threshold)
+		})
+		.map(|(address, _)| *address)
+		.collect();
+	if lagging_addresses.is_empty() {
+		return;
+	}
+
+	// Dropping the outbox closes the connection on the postmaster's side
+	for address in lagging_addresses {
+		clients.remove(&address);
+		warn!("Disconnected lagging client: {}", address);
+	}
+
+	announce_active_players(clients);
+}
+
+pub(super) fn send_shutdown_notice(clients: &mut ClientsMap, address: &SocketAddr) {
+	debug!("===== Send shutdown notice");
+
+	let Some(client) = clients.get(address) else {
+		return;
+	};
+
+	let ics = &client.individual_channel_sender;
+	let send = ics
+		.send(InternalMessage {
+			payload: InternalMessageAction::ResponseServerShuttingDown,
+			..Default::default()
+		})
+		.and_then(|_| {
+			ics.send(InternalMessage {
+				payload: InternalMessageAction::CloseConnection,
+				..Default::default()
+			})
+		});
+
+	// Dropping the outbox closes the connection without a notice
+	if send.is_err() {
+		clients.remove(address);
+		warn!("Could not send shutdown notice to: {}", address);
+	}
+}
+
 pub(super) fn announce_active_players(clients: &ClientsMap) {
 	debug!("===== Announce active players");
 
-	let players = get_cloned_map_of_players(&clients);
+	let players = get_cloned_map_of_players(clients);
 
 	for (address, client) in clients {
 		let ics = &client.individual_channel_sender;
@@ -186,13 +648,48 @@ pub(super) fn announce_active_players(clients: &ClientsMap) {
 	}
 }
 
-pub(super) fn announce_updated_choices(clients: &ClientsMap, choices: ChoicesMap) {
+pub(super) fn announce_game_state(
+	database: &DatabaseAccess,
+	room: &Room,
+	clients: &ClientsMap,
+	timer: &Option<RoundTimer>,
+) {
+	debug!("===== Announce game state");
+
+	let game_state = match compile_full_game_state(database, room, clients, timer) {
+		Ok(game_state) => game_state,
+		Err(error) => {
+			warn!("Could not compile game state: {}", error);
+			return;
+		}
+	};
+
+	for (address, client) in clients {
+		let ics = &client.individual_channel_sender;
+		let send = ics.send(InternalMessage {
+			payload: InternalMessageAction::ResponseGameState(Box::new(filter_game_state(
+				client,
+				game_state.clone(),
+			))),
+			..Default::default()
+		});
+		if send.is_err() {
+			warn!("Could not announce game state to: {}", address);
+		}
+	}
+}
+
+pub(super) fn announce_updated_choices(clients: &ClientsMap, round: &Round, choices: ChoicesMap) {
 	debug!("===== Announce updated choices");
 
 	for (address, client) in clients {
 		let ics = &client.individual_channel_sender;
 		let send = ics.send(InternalMessage {
-			payload: InternalMessageAction::ResponseUpdatedChoices(choices.clone()),
+			payload: InternalMessageAction::ResponseUpdatedChoices(filter_choices(
+				client,
+				Some(round),
+				&choices,
+			)),
 			..Default::default()
 		});
 		if send.is_err() {
@@ -204,7 +701,7 @@ pub(super) fn announce_updated_choices(clients: &ClientsMap, choices: ChoicesMap
 pub(super) fn announce_choice_to_organizers(clients: &ClientsMap, player: Player, choice: Choice) {
 	debug!("===== Announce choice to organizers");
 
-	let organizers = get_organizers(&clients);
+	let organizers = get_organizers(clients);
 
 	for (address, client) in organizers {
 		let ics = &client.individual_channel_sender;
@@ -218,6 +715,40 @@ pub(super) fn announce_choice_to_organizers(clients: &ClientsMap, player: Player
 	}
 }
 
+pub(super) fn announce_game_plan_to_organizers(clients: &ClientsMap, plan: Vec<PlannedRound>) {
+	debug!("===== Announce game plan to organizers");
+
+	let organizers = get_organizers(clients);
+
+	for (address, client) in organizers {
+		let ics = &client.individual_channel_sender;
+		let send = ics.send(InternalMessage {
+			payload: InternalMessageAction::ResponseGamePlan(plan.clone()),
+			..Default::default()
+		});
+		if send.is_err() {
+			warn!("Could not announce game plan to organizer: {}", address);
+		}
+	}
+}
+
+pub(super) fn announce_games_to_organizers(clients: &ClientsMap, games: Vec<Game>) {
+	debug!("===== Announce games to organizers");
+
+	let organizers = get_organizers(clients);
+
+	for (address, client) in organizers {
+		let ics = &client.individual_channel_sender;
+		let send = ics.send(InternalMessage {
+			payload: InternalMessageAction::ResponseGames(games.clone()),
+			..Default::default()
+		});
+		if send.is_err() {
+			warn!("Could not announce games to organizer: {}", address);
+		}
+	}
+}
+
 pub(super) fn announce_updated_player(clients: &ClientsMap, player: Player) {
 	debug!("===== Announce updated players");
 
@@ -233,24 +764,103 @@ pub(super) fn announce_updated_player(clients: &ClientsMap, player: Player) {
 	}
 }
 
+pub(super) fn announce_round_result(clients: &ClientsMap, result: RoundResult) {
+	debug!("===== Announce round result");
+
+	for (address, client) in clients {
+		let ics = &client.individual_channel_sender;
+		let send = ics.send(InternalMessage {
+			payload: InternalMessageAction::ResponseRoundResult(result.clone()),
+			..Default::default()
+		});
+		if send.is_err() {
+			warn!("Could not announce round result to: {}", address);
+		}
+	}
+}
+
+pub(super) fn announce_timer(clients: &ClientsMap, timer: &Option<RoundTimer>) {
+	debug!("===== Announce timer");
+
+	for (address, client) in clients {
+		let ics = &client.individual_channel_sender;
+		let send = ics.send(InternalMessage {
+			payload: InternalMessageAction::ResponseTimer(timer.as_ref().map(RoundTimer::status)),
+			..Default::default()
+		});
+		if send.is_err() {
+			warn!("Could not announce timer to: {}", address);
+		}
+	}
+}
+
+pub(super) fn respond_not_okay(
+	clients: &ClientsMap,
+	address: &SocketAddr,
+	response_id: ResponseIdentifier,
+	error: GameError,
+) -> Result<()> {
+	let ics = get_individual_channel_sender(clients, address)?;
+	ics.send(InternalMessage {
+		payload: InternalMessageAction::ResponseNotOkay(error),
+		response_id,
+	})
+}
+
+// Turn a failed request into an "ng" for the client that sent it
+pub(super) fn report_error(
+	rooms: &RoomsMap,
+	requester: Option<SocketAddr>,
+	response_id: ResponseIdentifier,
+	error: anyhow::Error,
+) {
+	debug!("===== Report error");
+
+	let error = match error.downcast::<GameError>() {
+		Ok(error) => {
+			info!(
+				"Refused request ({}): {}",
+				error.code.as_str(),
+				error.message
+			);
+			error
+		}
+		Err(error) => {
+			error!("{:#}", error);
+			GameError::internal()
+		}
+	};
+
+	// The client may already be gone, or the message never came from one
+	let Some(address) = requester else {
+		return;
+	};
+	let Some(game_room) = find_client_room_id(rooms, &address).and_then(|id| rooms.get(&id)) else {
+		return;
+	};
+	if respond_not_okay(&game_room.clients, &address, response_id, error).is_err() {
+		warn!("Could not report an error to: {}", address);
+	}
+}
+
 pub(super) fn announce_round(
 	database: &DatabaseAccess,
+	room_id: i64,
 	clients: &ClientsMap,
 	round: Option<Round>,
 ) {
 	debug!("===== Announce round");
 
-	let announce_round;
-	if round.is_none() {
-		let active_round = get_active_round(database);
-		if active_round.is_err() {
-			warn!("Could not get active round: {}", active_round.unwrap_err());
-			return;
-		}
-		announce_round = active_round.unwrap();
-	} else {
-		announce_round = round.unwrap();
-	}
+	let announce_round = match round {
+		Some(round) => round,
+		None => match get_active_round(database, room_id) {
+			Ok(active_round) => active_round,
+			Err(error) => {
+				warn!("Could not get active round: {}", error);
+				return;
+			}
+		},
+	};
 
 	for (address, client) in clients {
 		let ics = &client.individual_channel_sender;
@@ -264,23 +874,285 @@ pub(super) fn announce_round(
 	}
 }
 
+// Returns the players it changed, to be announced once the transaction is committed
 pub(super) fn allow_all_active_players_to_vote(
-	database: &DatabaseAccess,
+	db_access: &dyn Database,
+	room_id: i64,
 	clients: &ClientsMap,
-) -> Result<()> {
+) -> Result<Vec<Player>> {
 	debug!("===== Allow all active players to vote");
 
-	let db_access = acquire_database_lock(database)?;
+	let mut updated_players = vec![];
 
-	for (_, client) in clients {
+	for client in clients.values() {
 		if client.player.is_none() {
 			continue;
 		}
 
 		let player = client.player.as_ref().unwrap();
-		let updated_player = db_access.mark_player(player.id, None, Some(true))?;
-		announce_updated_player(clients, updated_player);
+		updated_players.push(db_access.mark_player(room_id, player.id, None, Some(true))?);
 	}
 
-	Ok(())
+	Ok(updated_players)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use tokio::sync::mpsc::channel;
+
+	use super::*;
+	use crate::{
+		config::config::ServerConfig,
+		database::{database::transaction, memory::InMemoryDatabase},
+		gamemaster::types::DEFAULT_ROOM_ID,
+	};
+
+	const ALL_STATES: [RoundState; 8] = [
+		RoundState::Standby,
+		RoundState::ShowQuestion,
+		RoundState::ShowChoices,
+		RoundState::VotingTime,
+		RoundState::VotingLocked,
+		RoundState::ShowVotes,
+		RoundState::Defense,
+		RoundState::ShowResults,
+	];
+
+	fn round(state: RoundState, options: usize) -> Round {
+		Round {
+			id: 1,
+			number: 1,
+			phase: 1,
+			state,
+			question: "Which one?".to_owned(),
+			options: (0..options)
+				.map(|index| format!("Option {}", index))
+				.collect(),
+		}
+	}
+
+	// Player ids with the option they picked and whether it was marked as a lie
+	fn choices(votes: &[(i64, u8, bool)]) -> ChoicesMap {
+		votes
+			.iter()
+			.map(|(player_id, option, lie)| {
+				(
+					*player_id,
+					Choice {
+						id: *player_id * 10,
+						option: ChoiceOption(*option),
+						lie: *lie,
+					},
+				)
+			})
+			.collect()
+	}
+
+	fn client(status: ClientStatus, player: Option<i64>, organizer: bool) -> Client {
+		let (sender, _) = channel(1);
+
+		Client {
+			individual_channel_sender: ClientOutbox::new(sender),
+			status,
+			player: player.map(|id| Player {
+				id,
+				name: format!("player{}", id),
+				points: Some(0),
+				can_vote: Some(true),
+			}),
+			organizer: organizer.then(|| Organizer {
+				id: 1,
+				username: "alice".to_owned(),
+				name: "Alice".to_owned(),
+			}),
+		}
+	}
+
+	fn visible_players(choices: &ChoicesMap) -> Vec<i64> {
+		let mut players: Vec<i64> = choices.keys().copied().collect();
+		players.sort();
+
+		players
+	}
+
+	#[test]
+	fn fewest_votes_win_without_the_lies() {
+		// Counting the lie would make it a tie
+		let result = compute_round_result(
+			&round(RoundState::ShowResults, 2),
+			&choices(&[(1, 0, false), (2, 0, true), (3, 1, false), (4, 1, false)]),
+		);
+
+		assert_eq!(result.round_id, 1);
+		assert_eq!(result.minority, Some(ChoiceOption(0)));
+		assert_eq!(result.votes, vec![1, 2]);
+		assert_eq!(result.winners, vec![1]);
+		assert_eq!(result.losers, vec![3, 4]);
+	}
+
+	#[test]
+	fn options_without_votes_do_not_win() {
+		let result = compute_round_result(
+			&round(RoundState::ShowResults, 3),
+			&choices(&[(5, 1, false), (3, 0, false), (4, 0, false)]),
+		);
+
+		assert_eq!(result.minority, Some(ChoiceOption(1)));
+		assert_eq!(result.votes, vec![2, 1, 0]);
+		assert_eq!(result.winners, vec![5]);
+		assert_eq!(result.losers, vec![3, 4]);
+	}
+
+	#[test]
+	fn a_tie_for_fewest_votes_is_a_draw() {
+		let result = compute_round_result(
+			&round(RoundState::ShowResults, 3),
+			&choices(&[(1, 0, false), (2, 1, false), (3, 2, false), (4, 2, false)]),
+		);
+
+		assert_eq!(result.minority, None);
+		assert_eq!(result.votes, vec![1, 1, 2]);
+		assert!(result.winners.is_empty());
+		assert!(result.losers.is_empty());
+	}
+
+	#[test]
+	fn fewer_than_two_picked_options_is_a_draw() {
+		let one_option = compute_round_result(
+			&round(RoundState::ShowResults, 2),
+			&choices(&[(1, 1, false), (2, 1, false), (3, 0, true)]),
+		);
+		assert_eq!(one_option.minority, None);
+		assert_eq!(one_option.votes, vec![0, 2]);
+		assert!(one_option.winners.is_empty());
+		assert!(one_option.losers.is_empty());
+
+		let no_votes = compute_round_result(&round(RoundState::ShowResults, 2), &choices(&[]));
+		assert_eq!(no_votes.minority, None);
+		assert_eq!(no_votes.votes, vec![0, 0]);
+	}
+
+	#[test]
+	fn a_round_is_only_scored_once() -> Result<()> {
+		let database = InMemoryDatabase::new();
+		let config: ConfigAccess = Arc::new(ServerConfig {
+			points_per_win: 3,
+			..Default::default()
+		});
+
+		let winner = database.create_player(DEFAULT_ROOM_ID, "winner")?;
+		let loser = database.create_player(DEFAULT_ROOM_ID, "loser")?;
+		let other_loser = database.create_player(DEFAULT_ROOM_ID, "other")?;
+		let liar = database.create_player(DEFAULT_ROOM_ID, "liar")?;
+		let absent = database.create_player(DEFAULT_ROOM_ID, "absent")?;
+		for player in [&winner, &loser, &other_loser, &liar, &absent] {
+			database.mark_player(DEFAULT_ROOM_ID, player.id, Some(1), Some(true))?;
+		}
+
+		let round = database.create_round(
+			DEFAULT_ROOM_ID,
+			1,
+			1,
+			RoundState::ShowResults,
+			"Which one?".to_owned(),
+			vec!["A".to_owned(), "B".to_owned()],
+		)?;
+		database.update_or_create_choice(round.id, winner.id, ChoiceOption(0))?;
+		database.update_or_create_choice(round.id, loser.id, ChoiceOption(1))?;
+		database.update_or_create_choice(round.id, other_loser.id, ChoiceOption(1))?;
+		let lie = database.update_or_create_choice(round.id, liar.id, ChoiceOption(0))?;
+		database.mark_choice(lie.id, Some(true))?;
+
+		// Like save_round each time the round enters its results
+		let resolve = || {
+			transaction(&database, |db_access| {
+				let result =
+					compute_round_result(&round, &db_access.get_choices_by_round_id(round.id)?);
+				if db_access.mark_round_resolved(round.id)? {
+					return apply_round_result(db_access, &config, DEFAULT_ROOM_ID, &result);
+				}
+
+				Ok(vec![])
+			})
+		};
+
+		let scored: Vec<i64> = resolve()?.iter().map(|player| player.id).collect();
+		assert_eq!(scored, vec![winner.id, loser.id, other_loser.id]);
+		assert!(resolve()?.is_empty());
+
+		let find = |id| database.find_player_by_id(DEFAULT_ROOM_ID, id);
+		let winner = find(winner.id)?.unwrap();
+		assert_eq!(winner.points, Some(4));
+		assert_eq!(winner.can_vote, Some(true));
+		for id in [loser.id, other_loser.id] {
+			let player = find(id)?.unwrap();
+			assert_eq!(player.points, Some(1));
+			assert_eq!(player.can_vote, Some(false));
+		}
+
+		// Neither winners nor losers
+		for id in [liar.id, absent.id] {
+			let player = find(id)?.unwrap();
+			assert_eq!(player.points, Some(1));
+			assert_eq!(player.can_vote, Some(true));
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn rounds_start_at_standby() {
+		assert!(check_round_transition(None, &RoundState::Standby).is_ok());
+		for state in &ALL_STATES[1..] {
+			assert!(check_round_transition(None, state).is_err());
+		}
+	}
+
+	#[test]
+	fn rounds_only_move_one_state_forward() {
+		for (position, current) in ALL_STATES.iter().enumerate() {
+			for (next_position, next) in ALL_STATES.iter().enumerate() {
+				let allowed = next_position == position || next_position == position + 1;
+				assert_eq!(
+					check_round_transition(Some(current), next).is_ok(),
+					allowed,
+					"{} to {}",
+					current.as_str(),
+					next.as_str()
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn choices_are_hidden_until_the_votes_are_shown() {
+		let all_choices = choices(&[(1, 0, false), (2, 1, false), (3, 1, true)]);
+		let organizer = client(ClientStatus::Registered, None, true);
+		let player = client(ClientStatus::Registered, Some(2), false);
+		let display = client(ClientStatus::Display, None, false);
+		let unregistered = client(ClientStatus::Unregistered, None, false);
+
+		let no_round = None;
+		let hidden = ALL_STATES[..5].iter().map(|state| round(state.clone(), 2));
+		for round in hidden {
+			for round in [Some(&round), no_round] {
+				let visible =
+					|client| visible_players(&filter_choices(client, round, &all_choices));
+				assert_eq!(visible(&organizer), vec![1, 2, 3]);
+				assert_eq!(visible(&player), vec![2]);
+				assert!(visible(&display).is_empty());
+				assert!(visible(&unregistered).is_empty());
+			}
+		}
+
+		let shown = ALL_STATES[5..].iter().map(|state| round(state.clone(), 2));
+		for round in shown {
+			for client in [&organizer, &player, &display, &unregistered] {
+				let visible = visible_players(&filter_choices(client, Some(&round), &all_choices));
+				assert_eq!(visible, vec![1, 2, 3], "{}", round.state.as_str());
+			}
+		}
+	}
 }
@@ -1,3 +1,10 @@
+pub mod credentials;
+pub mod error;
+#[allow(clippy::module_inception)]
 pub mod gamemaster;
 mod helpers;
+pub mod outbox;
+pub mod plan;
+pub mod supervisor;
+pub mod timer;
 pub mod types;
@@ -0,0 +1,52 @@
+use std::sync::{
+	atomic::{AtomicUsize, Ordering},
+	Arc,
+};
+
+use anyhow::{bail, Result};
+use tokio::sync::mpsc::{error::TrySendError, Sender};
+
+use crate::{config::config::SlowClientPolicy, postmaster::types::InternalMessage};
+
+// Bounded queue of messages waiting to be written to one client's socket.
+// The gamemaster never waits on it: when it is full the message is dropped instead.
+#[derive(Debug, Clone)]
+pub struct ClientOutbox {
+	sender: Sender<InternalMessage>,
+	dropped_in_a_row: Arc<AtomicUsize>,
+}
+
+impl ClientOutbox {
+	pub fn new(sender: Sender<InternalMessage>) -> Self {
+		Self {
+			sender,
+			dropped_in_a_row: Arc::new(AtomicUsize::new(0)),
+		}
+	}
+
+	pub fn send(&self, message: InternalMessage) -> Result<()> {
+		match self.sender.try_send(message) {
+			Ok(()) => {
+				self.dropped_in_a_row.store(0, Ordering::Relaxed);
+				Ok(())
+			}
+			Err(TrySendError::Full(_)) => {
+				let dropped = self.dropped_in_a_row.fetch_add(1, Ordering::Relaxed) + 1;
+				bail!(
+					"Client outbox is full, dropped message ({} in a row)",
+					dropped
+				)
+			}
+			Err(TrySendError::Closed(_)) => bail!("Client outbox is closed"),
+		}
+	}
+
+	pub fn is_lagging(&self, policy: SlowClientPolicy, threshold: usize) -> bool {
+		match policy {
+			SlowClientPolicy::Drop => false,
+			SlowClientPolicy::Disconnect => {
+				self.dropped_in_a_row.load(Ordering::Relaxed) >= threshold
+			}
+		}
+	}
+}
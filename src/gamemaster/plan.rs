@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Context, Result};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{helpers::check_round_options, timer::MAX_DURATION, types::PlannedRound};
+
+#[derive(Serialize, Deserialize, JsonSchema, TS, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanFormat {
+	Toml,
+	Json,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PlanFile {
+	rounds: Vec<PlanFileRound>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PlanFileRound {
+	number: u8,
+	#[serde(default = "first_phase")]
+	phase: u8,
+	question: String,
+	#[serde(default)]
+	options: Vec<String>,
+	// Shorthand for rounds with two options
+	choice_a: Option<String>,
+	choice_b: Option<String>,
+	voting_seconds: Option<u64>,
+}
+
+fn first_phase() -> u8 {
+	1
+}
+
+// Read a game plan file, sorted in the order its rounds are played
+pub fn parse_game_plan(format: PlanFormat, content: &str) -> Result<Vec<PlannedRound>> {
+	let file: PlanFile = match format {
+		PlanFormat::Toml => toml::from_str(content).context("Invalid TOML")?,
+		PlanFormat::Json => serde_json::from_str(content).context("Invalid JSON")?,
+	};
+
+	if file.rounds.is_empty() {
+		bail!("The game plan has no rounds");
+	}
+
+	let mut seen = HashSet::new();
+	let mut plan = Vec::with_capacity(file.rounds.len());
+	for round in file.rounds {
+		if round.number == 0 || round.phase == 0 {
+			bail!("Round and phase numbers start at 1");
+		}
+		if !seen.insert((round.number, round.phase)) {
+			bail!("Round {}-{} is listed twice", round.number, round.phase);
+		}
+		if round.question.trim().is_empty() {
+			bail!("Round {}-{} needs a question", round.number, round.phase);
+		}
+
+		let has_choices = round.choice_a.is_some() || round.choice_b.is_some();
+		if has_choices && !round.options.is_empty() {
+			bail!(
+				"Round {}-{} lists both options and choice_a/choice_b",
+				round.number,
+				round.phase
+			);
+		}
+		let options = if has_choices {
+			round.choice_a.into_iter().chain(round.choice_b).collect()
+		} else {
+			round.options
+		};
+		check_round_options(&options)
+			.with_context(|| format!("Round {}-{}", round.number, round.phase))?;
+		if round.voting_seconds == Some(0) {
+			bail!(
+				"The voting time of round {}-{} has to be at least one second",
+				round.number,
+				round.phase
+			);
+		}
+		if round
+			.voting_seconds
+			.is_some_and(|seconds| seconds > MAX_DURATION.as_secs())
+		{
+			bail!(
+				"The voting time of round {}-{} can be at most {} seconds",
+				round.number,
+				round.phase,
+				MAX_DURATION.as_secs()
+			);
+		}
+
+		plan.push(PlannedRound {
+			number: round.number,
+			phase: round.phase,
+			question: round.question,
+			options,
+			voting_seconds: round.voting_seconds,
+		});
+	}
+
+	plan.sort_by_key(|round| (round.number, round.phase));
+
+	Ok(plan)
+}
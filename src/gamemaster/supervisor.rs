@@ -0,0 +1,137 @@
+use std::{
+	any::Any,
+	panic::AssertUnwindSafe,
+	time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use futures_util::FutureExt;
+use log::{error, warn};
+use tokio::{sync::mpsc::Receiver, time::sleep};
+
+use crate::{
+	config::config::ConfigAccess, database::database::DatabaseAccess,
+	postmaster::types::InternalMessage,
+};
+
+use super::{
+	gamemaster::run_gamemaster,
+	helpers::{acquire_database_lock, announce_active_players, announce_game_state},
+	types::{GameRoom, GamemasterState},
+};
+
+// Waited before the first restart, doubling with each failure in a row
+const FIRST_RESTART_DELAY: Duration = Duration::from_millis(100);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(10);
+// A fault that keeps coming back is not going away by restarting
+const MAX_CONSECUTIVE_FAILURES: u32 = 8;
+// Failures further apart than this are not counted as in a row
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+pub async fn supervise_gamemaster(
+	mut gm_channel_receiver: Receiver<InternalMessage>,
+	database: DatabaseAccess,
+	config: ConfigAccess,
+) -> Result<()> {
+	// Owned here so the inbox and the client channels outlive a crashed gamemaster
+	let mut state = GamemasterState::default();
+	let mut consecutive_failures = 0;
+
+	loop {
+		let started_at = Instant::now();
+		let run = AssertUnwindSafe(run_gamemaster(
+			&mut gm_channel_receiver,
+			&mut state,
+			&database,
+			&config,
+		))
+		.catch_unwind()
+		.await;
+
+		match run {
+			Ok(Ok(())) => break,
+			Ok(Err(error)) => error!("Gamemaster stopped with an error: {:#}", error),
+			Err(panic) => error!("Gamemaster panicked: {}", describe_panic(&panic)),
+		}
+
+		if started_at.elapsed() >= HEALTHY_RUN {
+			consecutive_failures = 0;
+		}
+		consecutive_failures += 1;
+		if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+			bail!(
+				"Gamemaster failed {} times in a row, giving up",
+				consecutive_failures
+			);
+		}
+
+		let delay = FIRST_RESTART_DELAY
+			.saturating_mul(1 << (consecutive_failures - 1))
+			.min(MAX_RESTART_DELAY);
+		warn!("Restarting the gamemaster in {} ms", delay.as_millis());
+		sleep(delay).await;
+
+		restore_state(&database, &mut state);
+		let connections: usize = state
+			.rooms
+			.values()
+			.map(|game_room| game_room.clients.len())
+			.sum();
+		warn!(
+			"Gamemaster restarted with {} connected clients",
+			connections
+		);
+	}
+
+	Ok(())
+}
+
+fn restore_state(database: &DatabaseAccess, state: &mut GamemasterState) {
+	// A panic while holding the lock poisons it, but the database itself is fine
+	database.clear_poison();
+
+	// The stored players are the source of truth, the in-memory copies may be half updated
+	match acquire_database_lock(database) {
+		Ok(db_access) => {
+			// Whatever was half done when the gamemaster stopped is undone
+			if let Err(error) = db_access.rollback_open_transactions() {
+				warn!("Could not roll back open transactions: {}", error);
+			}
+
+			for GameRoom { room, clients, .. } in state.rooms.values_mut() {
+				for client in clients.values_mut() {
+					let Some(player) = client.player.as_ref() else {
+						continue;
+					};
+
+					match db_access.find_player_by_id(room.id, player.id) {
+						Ok(stored_player) => client.player = stored_player,
+						Err(error) => warn!("Could not reload player {}: {}", player.id, error),
+					}
+				}
+			}
+		}
+		Err(error) => warn!("Could not reload players: {}", error),
+	}
+
+	// Resync every client with the stored round, players and choices of its room
+	for GameRoom {
+		room,
+		clients,
+		timer,
+	} in state.rooms.values()
+	{
+		announce_game_state(database, room, clients, timer);
+		announce_active_players(clients);
+	}
+}
+
+fn describe_panic(panic: &Box<dyn Any + Send>) -> &str {
+	if let Some(message) = panic.downcast_ref::<&str>() {
+		message
+	} else if let Some(message) = panic.downcast_ref::<String>() {
+		message
+	} else {
+		"unknown cause"
+	}
+}
@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::types::RoundState;
+
+// How often a running timer is announced to clients
+pub const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Longest a timer can run for, which keeps its deadline far from overflowing
+pub const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Countdown attached to one state of one round.
+// The round moves on to advance_to when it runs out.
+#[derive(Debug, Clone)]
+pub struct RoundTimer {
+	pub round_id: i64,
+	pub state: RoundState,
+	pub advance_to: RoundState,
+	// Time left when the timer was last paused or started
+	remaining: Duration,
+	// Set while the timer is running
+	deadline: Option<Instant>,
+	next_tick: Instant,
+}
+
+impl RoundTimer {
+	pub fn start(
+		round_id: i64,
+		state: RoundState,
+		advance_to: RoundState,
+		duration: Duration,
+	) -> Self {
+		let now = Instant::now();
+		let duration = duration.min(MAX_DURATION);
+
+		Self {
+			round_id,
+			state,
+			advance_to,
+			remaining: duration,
+			deadline: Some(now + duration),
+			next_tick: now + TICK_INTERVAL,
+		}
+	}
+
+	pub fn is_running(&self) -> bool {
+		self.deadline.is_some()
+	}
+
+	pub fn remaining(&self) -> Duration {
+		match self.deadline {
+			Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+			None => self.remaining,
+		}
+	}
+
+	pub fn is_expired(&self) -> bool {
+		self.is_running() && self.remaining().is_zero()
+	}
+
+	// Wall clock time the timer runs out at, for clients to count down on their own
+	pub fn deadline_time(&self) -> Option<SystemTime> {
+		self.deadline.map(|_| SystemTime::now() + self.remaining())
+	}
+
+	pub fn status(&self) -> TimerStatus {
+		TimerStatus {
+			round_id: self.round_id,
+			state: self.state.clone(),
+			advance_to: self.advance_to.clone(),
+			running: self.is_running(),
+			remaining_ms: self.remaining().as_millis() as u64,
+			deadline: self.deadline_time().map(|deadline| {
+				deadline
+					.duration_since(UNIX_EPOCH)
+					.unwrap_or_default()
+					.as_millis() as u64
+			}),
+		}
+	}
+
+	pub fn pause(&mut self) {
+		self.remaining = self.remaining();
+		self.deadline = None;
+	}
+
+	pub fn resume(&mut self) {
+		let now = Instant::now();
+		self.deadline = Some(now + self.remaining);
+		self.next_tick = now + TICK_INTERVAL;
+	}
+
+	// Extended timers are capped at MAX_DURATION
+	pub fn extend(&mut self, duration: Duration) {
+		let remaining = self.remaining().saturating_add(duration).min(MAX_DURATION);
+		match self.deadline.as_mut() {
+			Some(deadline) => *deadline = Instant::now() + remaining,
+			None => self.remaining = remaining,
+		}
+	}
+
+	// When the gamemaster should next look at this timer, None while paused
+	pub fn next_wake_up(&self) -> Option<Instant> {
+		self.deadline.map(|deadline| deadline.min(self.next_tick))
+	}
+
+	pub fn schedule_next_tick(&mut self) {
+		self.next_tick = Instant::now() + TICK_INTERVAL;
+	}
+}
+
+// What clients see of a timer at the moment it is announced
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct TimerStatus {
+	#[ts(type = "number")]
+	pub round_id: i64,
+	pub state: RoundState,
+	pub advance_to: RoundState,
+	pub running: bool,
+	#[ts(type = "number")]
+	pub remaining_ms: u64,
+	// Milliseconds since the epoch, so clients can count down between ticks
+	#[ts(type = "number | null")]
+	pub deadline: Option<u64>,
+}
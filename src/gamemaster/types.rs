@@ -1,18 +1,25 @@
-use std::{collections::HashMap, net::SocketAddr};
+use std::{collections::HashMap, net::SocketAddr, time::Instant};
 
-use crossbeam::channel::Sender;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use ts_rs::TS;
 
-use crate::postmaster::types::InternalMessage;
+use super::{
+	outbox::ClientOutbox,
+	timer::{RoundTimer, TimerStatus},
+};
 
 #[derive(Debug, Clone)]
 pub enum ClientStatus {
 	Unregistered,
 	Registered,
+	// Read-only spectator, e.g. a projector, never a player or organizer
+	Display,
 }
 
 #[derive(Debug, Clone)]
 pub struct Client {
-	pub individual_channel_sender: Sender<InternalMessage>,
+	pub individual_channel_sender: ClientOutbox,
 	pub status: ClientStatus,
 	pub player: Option<Player>,
 	pub organizer: Option<Organizer>,
@@ -20,20 +27,60 @@ pub struct Client {
 
 pub type ClientsMap = HashMap<SocketAddr, Client>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
 pub struct Player {
-	pub id: u8,
+	#[ts(type = "number")]
+	pub id: i64,
 	pub name: String,
 	pub points: Option<usize>,
 	pub can_vote: Option<bool>,
 }
 
-#[derive(Debug, Clone)]
+// The default room every connection starts in, also holding the data from before rooms
+pub const DEFAULT_ROOM_ID: i64 = 1;
+
+// A game running on the server, joined by its code
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+pub struct Room {
+	#[ts(type = "number")]
+	pub id: i64,
+	pub code: String,
+	pub name: String,
+}
+
+// One play-through of a room, with its own rounds and scores.
+// A room always has a current game, starting another archives it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Game {
+	#[ts(type = "number")]
+	pub id: i64,
+	pub name: String,
+	// Milliseconds since the Unix epoch
+	#[ts(type = "number")]
+	pub started_at: i64,
+	// None while it is the current game
+	#[ts(type = "number | null")]
+	pub archived_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 pub struct Organizer {
+	#[ts(type = "number")]
+	pub id: i64,
+	pub username: String,
 	pub name: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
+pub struct OrganizerAccount {
+	pub organizer: Organizer,
+	pub password_hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "kebab-case")]
 pub enum RoundState {
 	Standby,
 	ShowQuestion,
@@ -58,38 +105,155 @@ impl RoundState {
 			RoundState::ShowResults => "show-results",
 		}
 	}
+
+	// The state that follows this one, None once the results are shown
+	pub fn next(&self) -> Option<RoundState> {
+		match self {
+			RoundState::Standby => Some(RoundState::ShowQuestion),
+			RoundState::ShowQuestion => Some(RoundState::ShowChoices),
+			RoundState::ShowChoices => Some(RoundState::VotingTime),
+			RoundState::VotingTime => Some(RoundState::VotingLocked),
+			RoundState::VotingLocked => Some(RoundState::ShowVotes),
+			RoundState::ShowVotes => Some(RoundState::Defense),
+			RoundState::Defense => Some(RoundState::ShowResults),
+			RoundState::ShowResults => None,
+		}
+	}
 }
 
-#[derive(Debug, Clone)]
+// How many options a round can offer
+pub const MIN_ROUND_OPTIONS: usize = 2;
+pub const MAX_ROUND_OPTIONS: usize = 6;
+
+// Sent as a RoundMessage, which also carries the first two options as choiceA/choiceB
+#[derive(Debug, Clone, Deserialize, TS)]
+#[ts(as = "crate::postmaster::json::serializer::RoundMessage")]
 pub struct Round {
-	pub id: u8,
+	#[ts(type = "number")]
+	pub id: i64,
 	pub number: u8,
 	pub phase: u8,
 	pub state: RoundState,
 	pub question: String,
-	pub choice_a: String,
-	pub choice_b: String,
+	// In the order they are shown, a ChoiceOption points into this
+	#[serde(default)]
+	pub options: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
-pub enum ChoiceOption {
-	ChoiceA,
-	ChoiceB,
+// A round of the loaded game plan, created as a Round when the host moves on to it
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedRound {
+	pub number: u8,
+	pub phase: u8,
+	pub question: String,
+	pub options: Vec<String>,
+	// Starts a timer when the round reaches voting-time
+	#[ts(type = "number | null")]
+	pub voting_seconds: Option<u64>,
 }
 
-#[derive(Debug, Clone)]
+// Position of the picked option in the round's options.
+// Clients see it as a letter, 'a' being the first option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[ts(type = "string")]
+pub struct ChoiceOption(pub u8);
+
+impl ChoiceOption {
+	pub fn from_letter(letter: &str) -> Option<ChoiceOption> {
+		match letter.as_bytes() {
+			[byte @ b'a'..=b'z'] if usize::from(byte - b'a') < MAX_ROUND_OPTIONS => {
+				Some(ChoiceOption(byte - b'a'))
+			}
+			_ => None,
+		}
+	}
+
+	pub fn letter(&self) -> char {
+		char::from(b'a' + self.0)
+	}
+
+	pub fn index(&self) -> usize {
+		usize::from(self.0)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 pub struct Choice {
-	pub id: u8,
+	#[ts(type = "number")]
+	pub id: i64,
 	pub option: ChoiceOption,
 	pub lie: bool,
 }
 
-pub type ChoicesMap = HashMap<u8, Choice>;
-pub type PlayerMap = HashMap<u8, Player>;
+pub type ChoicesMap = HashMap<i64, Choice>;
 
-#[derive(Debug, Clone)]
+// One vote as it was submitted, a Choice only keeps the latest
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ChoiceEvent {
+	pub option: ChoiceOption,
+	// Milliseconds since the Unix epoch
+	#[ts(type = "number")]
+	pub submitted_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundResult {
+	#[ts(type = "number")]
+	pub round_id: i64,
+	// None on a draw
+	pub minority: Option<ChoiceOption>,
+	// Counted votes for each option, in the round's order.
+	// Clients see them keyed by option letter, like choices.
+	#[serde(with = "crate::postmaster::json::serializer::votes_by_letter")]
+	#[schemars(with = "HashMap<String, usize>")]
+	#[ts(type = "Record<string, number>")]
+	pub votes: Vec<usize>,
+	#[ts(type = "Array<number>")]
+	pub winners: Vec<i64>,
+	#[ts(type = "Array<number>")]
+	pub losers: Vec<i64>,
+}
+pub type PlayerMap = HashMap<i64, Player>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Session {
+	pub room_id: i64,
+	pub player_id: i64,
+	pub expires_at: Instant,
+}
+
+pub type SessionsMap = HashMap<String, Session>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 pub struct GameState {
+	pub room: Room,
+	pub game: Game,
 	pub round: Option<Round>,
+	#[ts(type = "{ [key in number]?: Player }")]
 	pub players: PlayerMap,
+	#[ts(type = "{ [key in number]?: Choice }")]
 	pub choices: ChoicesMap,
+	pub result: Option<RoundResult>,
+	pub timer: Option<TimerStatus>,
+}
+
+// The clients and timer of one room, every client is in exactly one room
+#[derive(Debug)]
+pub struct GameRoom {
+	pub room: Room,
+	pub clients: ClientsMap,
+	pub timer: Option<RoundTimer>,
+}
+
+pub type RoomsMap = HashMap<i64, GameRoom>;
+
+// Everything the gamemaster keeps in memory, kept by the supervisor across restarts
+#[derive(Debug, Default)]
+pub struct GamemasterState {
+	pub rooms: RoomsMap,
+	pub sessions: SessionsMap,
+	pub shutting_down: bool,
 }
@@ -1,44 +1,167 @@
 use std::{
+	fs,
+	path::Path,
 	process::exit,
 	sync::{Arc, Mutex},
-	thread,
 	time::Duration,
 };
 
-use crossbeam::channel::unbounded;
-use database::sqlite::SqliteDatabase;
-use env_logger::Env;
-use futures_util::future;
-use gamemaster::gamemaster::start_gamemaster;
-use log::{error, info};
-use postmaster::{postmaster::accept_connection, types::InternalMessage};
-use tokio::net::TcpListener;
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use config::{
+	arguments::{Arguments, Command},
+	config::{ConfigAccess, DatabaseBackend, ServerConfig},
+};
+use database::{
+	database::{Database, DatabaseAccess},
+	memory::InMemoryDatabase,
+	sqlite::SqliteDatabase,
+};
+use gamemaster::{credentials::hash_password, supervisor::supervise_gamemaster};
+use log::{error, info, warn};
+use postmaster::{
+	json::export::{protocol_schema, protocol_typescript},
+	postmaster::accept_connection,
+	types::{InternalMessage, InternalMessageAction},
+};
+use tokio::{net::TcpListener, signal, sync::mpsc::channel, time::timeout};
 
+mod config;
 mod database;
 mod gamemaster;
 mod postmaster;
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 8)]
-async fn main() {
+fn main() {
+	// Load configuration
+	let arguments = Arguments::parse();
+	let config = match ServerConfig::load(&arguments) {
+		Ok(config) => config,
+		Err(error) => {
+			eprintln!("Invalid configuration: {:#}", error);
+			exit(2);
+		}
+	};
+
 	// Setup logger
-	env_logger::Builder::from_env(Env::default().default_filter_or("debug")).init();
+	env_logger::Builder::new()
+		.parse_filters(&config.log_filter)
+		.init();
+
+	// Run one-off commands instead of the server
+	if let Some(command) = arguments.command {
+		if let Err(error) = run_command(&config, command) {
+			error!("{:#}", error);
+			exit(1);
+		}
+		return;
+	}
+
+	// Setup runtime
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.worker_threads(config.worker_threads)
+		.enable_all()
+		.build()
+		.expect("Could not build the tokio runtime");
+
+	runtime.block_on(serve(Arc::new(config)));
+}
+
+fn run_command(config: &ServerConfig, command: Command) -> Result<()> {
+	match command {
+		Command::AddOrganizer {
+			username,
+			name,
+			password,
+		} => {
+			if username.trim().is_empty() || password.is_empty() {
+				bail!("The organizer username and password must not be empty");
+			}
+
+			let database = SqliteDatabase::new(&config.database_path);
+			if database.find_organizer_by_username(&username)?.is_some() {
+				bail!("An organizer named {} already exists", username);
+			}
+
+			let name = name.unwrap_or_else(|| username.clone());
+			let organizer =
+				database.create_organizer(&username, &hash_password(&password)?, &name)?;
+			info!(
+				"Created organizer {} ({})",
+				organizer.username, organizer.name
+			);
+		}
+		Command::PendingMigrations => {
+			let database = SqliteDatabase::open(&config.database_path)?;
+			let pending = database.pending_migrations()?;
+			if pending.is_empty() {
+				println!("The database is up to date");
+			}
+			for migration in pending {
+				println!("{}: {}", migration.version, migration.description);
+			}
+		}
+		Command::ExportProtocol {
+			schema,
+			typescript,
+			check,
+		} => {
+			for (path, content) in [
+				(schema, protocol_schema()),
+				(typescript, protocol_typescript()?),
+			] {
+				if check {
+					let existing = fs::read_to_string(&path)
+						.with_context(|| format!("Could not read {}", path.display()))?;
+					if existing != content {
+						bail!(
+							"{} is out of date, run export-protocol to update it",
+							path.display()
+						);
+					}
+					continue;
+				}
+
+				fs::write(&path, content)
+					.with_context(|| format!("Could not write {}", path.display()))?;
+				info!("Wrote {}", path.display());
+			}
+		}
+	}
+
+	Ok(())
+}
 
+async fn serve(config: ConfigAccess) {
 	// Setup database
-	let database = SqliteDatabase::new("database.db");
-	let database_arc = Arc::new(Mutex::new(database));
+	let database_arc: DatabaseAccess = match config.database_backend {
+		DatabaseBackend::Sqlite => Arc::new(Mutex::new(SqliteDatabase::new(&config.database_path))),
+		DatabaseBackend::Memory => match open_memory_database(&config.database_path) {
+			Ok(database) => Arc::new(Mutex::new(database)),
+			Err(error) => {
+				error!("{:#}", error);
+				exit(1);
+			}
+		},
+	};
 
-	// Create crossbeam channels for communicating with gamemaster
-	let (gm_channel_sender, gm_channel_receiver) = unbounded::<InternalMessage>();
+	// Create the gamemaster's inbox; connections wait when it is full
+	let (gm_channel_sender, gm_channel_receiver) =
+		channel::<InternalMessage>(config.gamemaster_inbox_size);
 
-	// Run gamemaster in new thread
-	let gamemaster_handle = tokio::spawn(start_gamemaster(
-		gm_channel_receiver.clone(),
+	// Run gamemaster as its own task, restarting it when it fails
+	let mut gamemaster_handle = tokio::spawn(supervise_gamemaster(
+		gm_channel_receiver,
 		database_arc.clone(),
+		config.clone(),
 	));
 
-	let addr = "0.0.0.0:9002";
-	let listener = TcpListener::bind(&addr).await.expect("Can't listen");
-	info!("Listening on: {}", addr);
+	let listener = TcpListener::bind(&config.listen_address)
+		.await
+		.expect("Can't listen");
+	info!("Listening on: {}", config.listen_address);
+
+	let shutdown = shutdown_signal();
+	tokio::pin!(shutdown);
 
 	loop {
 		tokio::select! {
@@ -47,21 +170,101 @@ async fn main() {
 				let peer = stream.peer_addr().expect("Connected streams should have a peer address");
 				info!("Peer address: {}", peer);
 
-				// Create a dedicated thread
-				let cloned_gm_channel_sender = gm_channel_sender.clone();
-				thread::spawn(move || { accept_connection(peer, stream, cloned_gm_channel_sender) });
+				// Serve the connection as a task on this runtime
+				tokio::spawn(accept_connection(peer, stream, gm_channel_sender.clone(), config.clone()));
 			},
 
-			// Monitor gamemaster thread, end everything if it's dead
-			gm_handle_finished = future::lazy(|_| gamemaster_handle.is_finished()) => {
-				if !gm_handle_finished {
-					thread::sleep(Duration::from_millis(50));
-					continue;
+			// Monitor gamemaster task, end everything if it's dead
+			stopped = &mut gamemaster_handle => {
+				match stopped {
+					Ok(Err(error)) => error!("Gamemaster task is dead: {:#}", error),
+					_ => error!("Gamemaster task is dead!"),
 				}
-
-				error!("Gamemaster thread is dead!");
 				exit(1);
 			}
+
+			// Stop on SIGINT or SIGTERM
+			_ = &mut shutdown => break,
 		}
 	}
+
+	// Stop accepting new connections
+	drop(listener);
+	info!("Shutting down");
+
+	// Let the gamemaster say goodbye to every client and drain its inbox
+	gm_channel_sender
+		.send(InternalMessage {
+			payload: InternalMessageAction::RequestShutdown,
+			..Default::default()
+		})
+		.await
+		.expect("Could not send shutdown request to GM");
+	drop(gm_channel_sender);
+
+	let grace_period = Duration::from_secs(config.shutdown_grace_period);
+	if timeout(grace_period, &mut gamemaster_handle).await.is_err() {
+		warn!(
+			"Clients did not disconnect within {} seconds, stopping anyway",
+			config.shutdown_grace_period
+		);
+		gamemaster_handle.abort();
+		let _ = gamemaster_handle.await;
+	}
+
+	// Nothing else uses the database now
+	let mut db_access = database_arc.lock().expect("Could not lock the database");
+	match db_access.close() {
+		Ok(()) => info!("Database closed"),
+		Err(error) => error!("{:#}", error),
+	}
+}
+
+// Starts empty apart from the organizer accounts, so they can still log in
+fn open_memory_database(database_path: &str) -> Result<InMemoryDatabase> {
+	warn!("Using the memory database backend, nothing is kept after the server stops");
+	let database = InMemoryDatabase::new();
+
+	// Opening the file would create it, and this backend leaves no trace
+	if !Path::new(database_path).exists() {
+		warn!(
+			"{} does not exist, there are no organizer accounts",
+			database_path
+		);
+		return Ok(database);
+	}
+
+	let accounts = SqliteDatabase::open(database_path)?
+		.get_organizer_accounts()
+		.with_context(|| format!("Could not read the organizers from {}", database_path))?;
+	for account in &accounts {
+		database.create_organizer(
+			&account.organizer.username,
+			&account.password_hash,
+			&account.organizer.name,
+		)?;
+	}
+	info!(
+		"Copied {} organizer accounts from {}",
+		accounts.len(),
+		database_path
+	);
+
+	Ok(database)
+}
+
+async fn shutdown_signal() {
+	#[cfg(unix)]
+	{
+		let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())
+			.expect("Could not listen for SIGTERM");
+
+		tokio::select! {
+			_ = signal::ctrl_c() => (),
+			_ = terminate.recv() => (),
+		}
+	}
+
+	#[cfg(not(unix))]
+	signal::ctrl_c().await.expect("Could not listen for SIGINT");
 }
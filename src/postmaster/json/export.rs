@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use schemars::{
+	gen::SchemaSettings,
+	schema::{RootSchema, SchemaObject, SubschemaValidation},
+};
+use ts_rs::TS;
+
+use crate::{
+	gamemaster::{
+		error::ErrorCode,
+		plan::PlanFormat,
+		timer::TimerStatus,
+		types::{
+			Choice, ChoiceEvent, ChoiceOption, Game, GameState, Organizer, PlannedRound, Player,
+			Room, Round, RoundResult, RoundState,
+		},
+	},
+	postmaster::{
+		protocol::{ClientHello, ClientKind, ServerHello},
+		types::{
+			LateVote, LoadPlan, LoginOrganizer, MarkChoice, MarkPlayer, PlayerChoice,
+			PlayerIdentity, PlayerPoints, SetRound, StartTimer, VoteHistory, VoteHistoryQuery,
+			WebSocketMessage, WebSocketMessageAction, WebSocketResponse, WebSocketResponseAction,
+		},
+	},
+};
+
+const GENERATED_NOTICE: &str = "Generated by `ucv-game export-protocol`, do not edit by hand.";
+
+// JSON Schema of every message, requests and responses alike
+pub fn protocol_schema() -> String {
+	let mut generator = SchemaSettings::draft07().into_generator();
+	let request = generator.subschema_for::<WebSocketMessage>();
+	let response = generator.subschema_for::<WebSocketResponse>();
+
+	let mut schema = SchemaObject {
+		subschemas: Some(Box::new(SubschemaValidation {
+			one_of: Some(vec![request, response]),
+			..Default::default()
+		})),
+		..Default::default()
+	};
+	let metadata = schema.metadata();
+	metadata.title = Some("UCV game protocol".to_owned());
+	metadata.description = Some(GENERATED_NOTICE.to_owned());
+
+	let root = RootSchema {
+		meta_schema: generator.settings().meta_schema.clone(),
+		schema,
+		definitions: generator.take_definitions(),
+	};
+	let mut json =
+		serde_json::to_string_pretty(&root).expect("Could not serialize the protocol schema");
+	json.push('\n');
+
+	json
+}
+
+// A single exported type, along with the types it refers to
+struct Declaration {
+	name: String,
+	typescript: String,
+	dependencies: Vec<String>,
+}
+
+fn declaration<T: TS + 'static>() -> Declaration {
+	Declaration {
+		name: T::ident(),
+		typescript: T::decl(),
+		dependencies: T::dependencies()
+			.into_iter()
+			.map(|dependency| dependency.ts_name)
+			.collect(),
+	}
+}
+
+// TypeScript declarations of every message, in the order they depend on each other
+pub fn protocol_typescript() -> Result<String> {
+	let declarations = [
+		declaration::<ErrorCode>(),
+		declaration::<ClientKind>(),
+		declaration::<ClientHello>(),
+		declaration::<ServerHello>(),
+		declaration::<Player>(),
+		declaration::<Room>(),
+		declaration::<Game>(),
+		declaration::<Organizer>(),
+		declaration::<RoundState>(),
+		declaration::<Round>(),
+		declaration::<PlannedRound>(),
+		declaration::<ChoiceOption>(),
+		declaration::<Choice>(),
+		declaration::<ChoiceEvent>(),
+		declaration::<RoundResult>(),
+		declaration::<TimerStatus>(),
+		declaration::<GameState>(),
+		declaration::<PlanFormat>(),
+		declaration::<LoginOrganizer>(),
+		declaration::<SetRound>(),
+		declaration::<LoadPlan>(),
+		declaration::<StartTimer>(),
+		declaration::<MarkChoice>(),
+		declaration::<MarkPlayer>(),
+		declaration::<PlayerPoints>(),
+		declaration::<LateVote>(),
+		declaration::<VoteHistoryQuery>(),
+		declaration::<WebSocketMessageAction>(),
+		declaration::<WebSocketMessage>(),
+		declaration::<PlayerIdentity>(),
+		declaration::<PlayerChoice>(),
+		declaration::<VoteHistory>(),
+		declaration::<WebSocketResponseAction>(),
+		declaration::<WebSocketResponse>(),
+	];
+
+	// Every referenced type has to be exported too, or the file will not compile
+	let declared: HashSet<&str> = declarations
+		.iter()
+		.map(|declaration| declaration.name.as_str())
+		.collect();
+	for declaration in &declarations {
+		for dependency in &declaration.dependencies {
+			if !declared.contains(dependency.as_str()) {
+				bail!(
+					"{} refers to {}, which is not exported",
+					declaration.name,
+					dependency
+				);
+			}
+		}
+	}
+
+	let mut typescript = format!("// {}\n", GENERATED_NOTICE);
+	for declaration in declarations {
+		typescript.push_str(&format!("\nexport {}\n", declaration.typescript));
+	}
+
+	Ok(typescript)
+}
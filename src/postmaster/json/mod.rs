@@ -1,3 +1,3 @@
+pub mod export;
 pub mod parser;
-pub mod response;
 pub mod serializer;
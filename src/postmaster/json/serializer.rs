@@ -1,227 +1,168 @@
-use serde::{
-	de::{self, MapAccess},
-	ser::SerializeStruct,
-	Serialize,
+use schemars::{
+	gen::SchemaGenerator,
+	schema::{InstanceType, Schema, SchemaObject, StringValidation},
+	JsonSchema,
 };
-use serde_json::json;
+use serde::Serialize;
+use ts_rs::TS;
 
-use crate::gamemaster::types::{
-	Choice, ChoiceOption, GameState, Organizer, Player, Round, RoundState,
-};
+use crate::gamemaster::types::{ChoiceOption, Round, RoundState, MAX_ROUND_OPTIONS};
 
-impl Serialize for Player {
+impl Serialize for ChoiceOption {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 	where
 		S: serde::Serializer,
 	{
-		let mut state = serializer.serialize_struct("Player", 3)?;
-		state.serialize_field("id", &self.id)?;
-		state.serialize_field("name", &self.name)?;
-		state.serialize_field("points", &self.points)?;
-		state.serialize_field("canVote", &self.can_vote)?;
-		state.end()
+		serializer.serialize_char(self.letter())
 	}
 }
 
-impl<'de> serde::de::Deserialize<'de> for Player {
+impl<'de> serde::de::Deserialize<'de> for ChoiceOption {
 	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
 	where
 		D: serde::Deserializer<'de>,
 	{
-		struct PlayerVisitor;
+		struct ChoiceOptionVisitor;
 
-		impl<'de> serde::de::Visitor<'de> for PlayerVisitor {
-			type Value = Player;
+		impl<'de> serde::de::Visitor<'de> for ChoiceOptionVisitor {
+			type Value = ChoiceOption;
 
 			fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-				formatter.write_str("a Player object")
+				formatter.write_str("an option letter or position")
+			}
+
+			fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+			where
+				E: serde::de::Error,
+			{
+				ChoiceOption::from_letter(value).ok_or(E::custom("invalid ChoiceOption letter"))
 			}
 
-			fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+			fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
 			where
-				V: MapAccess<'de>,
+				E: serde::de::Error,
 			{
-				let mut id: Option<u8> = None;
-				let mut name: Option<String> = None;
-				let mut points: Option<usize> = None;
-				let mut can_vote: Option<bool> = None;
-
-				while let Some(key) = map.next_key()? {
-					match key {
-						"id" => {
-							id = Some(map.next_value()?);
-						}
-						"name" => {
-							name = Some(map.next_value()?);
-						}
-						"points" => {
-							points = Some(map.next_value()?);
-						}
-						"canVote" => {
-							can_vote = Some(map.next_value()?);
-						}
-						_ => {
-							let _ = map.next_value()?;
-						}
-					}
-				}
-
-				if id.is_none() {
-					return Err(de::Error::missing_field("id"));
-				}
-				if name.is_none() {
-					name = Some("".to_owned());
-				}
-
-				Ok(Player {
-					id: id.unwrap(),
-					name: name.unwrap(),
-					points,
-					can_vote,
-				})
+				u8::try_from(value)
+					.ok()
+					.filter(|position| usize::from(*position) < MAX_ROUND_OPTIONS)
+					.map(ChoiceOption)
+					.ok_or(E::custom("invalid ChoiceOption position"))
 			}
 		}
 
-		deserializer.deserialize_map(PlayerVisitor)
+		deserializer.deserialize_any(ChoiceOptionVisitor)
 	}
 }
 
-impl Into<serde_json::Value> for Organizer {
-	fn into(self) -> serde_json::Value {
-		json!({
-			"name": self.name,
-		})
+// Described by its letter; positions are still accepted from older clients
+impl JsonSchema for ChoiceOption {
+	fn schema_name() -> String {
+		"ChoiceOption".to_owned()
 	}
-}
 
-impl Serialize for RoundState {
-	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-	where
-		S: serde::Serializer,
-	{
-		serializer.serialize_str(self.as_str())
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		let last_letter = ChoiceOption(MAX_ROUND_OPTIONS as u8 - 1).letter();
+		SchemaObject {
+			instance_type: Some(InstanceType::String.into()),
+			string: Some(Box::new(StringValidation {
+				pattern: Some(format!("^[a-{}]$", last_letter)),
+				..Default::default()
+			})),
+			..Default::default()
+		}
+		.into()
 	}
 }
 
-impl Serialize for Round {
-	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-	where
-		S: serde::Serializer,
-	{
-		let mut state = serializer.serialize_struct("Round", 7)?;
-		state.serialize_field("id", &self.id)?;
-		state.serialize_field("number", &self.number)?;
-		state.serialize_field("phase", &self.phase)?;
-		state.serialize_field("state", &self.state)?;
-		state.serialize_field("question", &self.question)?;
-		state.serialize_field("choiceA", &self.choice_a)?;
-		state.serialize_field("choiceB", &self.choice_b)?;
-		state.end()
-	}
+// What clients see of a Round
+#[derive(Serialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "Round")]
+pub struct RoundMessage {
+	#[ts(type = "number")]
+	id: i64,
+	number: u8,
+	phase: u8,
+	state: RoundState,
+	question: String,
+	options: Vec<String>,
+	// For clients that only know about two choices
+	choice_a: Option<String>,
+	choice_b: Option<String>,
 }
 
-impl Serialize for ChoiceOption {
-	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-	where
-		S: serde::Serializer,
-	{
-		let option = match self {
-			ChoiceOption::ChoiceA => 'a',
-			ChoiceOption::ChoiceB => 'b',
-		};
-
-		serializer.serialize_char(option)
+impl From<Round> for RoundMessage {
+	fn from(round: Round) -> Self {
+		let mut options = round.options.iter().cloned();
+		let choice_a = options.next();
+		let choice_b = options.next();
+
+		RoundMessage {
+			id: round.id,
+			number: round.number,
+			phase: round.phase,
+			state: round.state,
+			question: round.question,
+			options: round.options,
+			choice_a,
+			choice_b,
+		}
 	}
 }
 
-impl Serialize for Choice {
+impl Serialize for Round {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 	where
 		S: serde::Serializer,
 	{
-		let mut state = serializer.serialize_struct("Choice", 3)?;
-		state.serialize_field("id", &self.id)?;
-		state.serialize_field("option", &self.option)?;
-		state.serialize_field("lie", &self.lie)?;
-		state.end()
+		RoundMessage::from(self.clone()).serialize(serializer)
 	}
 }
 
-impl Serialize for GameState {
-	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-	where
-		S: serde::Serializer,
-	{
-		let mut state = serializer.serialize_struct("GameState", 3)?;
-		state.serialize_field("round", &self.round)?;
-		state.serialize_field("players", &self.players)?;
-		state.serialize_field("choices", &self.choices)?;
-		state.end()
+impl JsonSchema for Round {
+	fn schema_name() -> String {
+		RoundMessage::schema_name()
 	}
-}
 
-impl<'de> serde::de::Deserialize<'de> for RoundState {
-	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-	where
-		D: serde::Deserializer<'de>,
-	{
-		struct RoundStateVisitor;
+	fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+		RoundMessage::json_schema(generator)
+	}
+}
 
-		impl<'de> serde::de::Visitor<'de> for RoundStateVisitor {
-			type Value = RoundState;
+// Vote counts in the round's order, keyed by option letter on the wire
+pub mod votes_by_letter {
+	use std::collections::BTreeMap;
 
-			fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-				formatter.write_str("a RoundState string")
-			}
+	use serde::{de, Deserialize, Deserializer, Serializer};
 
-			fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-			where
-				E: serde::de::Error,
-			{
-				match value {
-					"standby" => Ok(RoundState::Standby),
-					"show-question" => Ok(RoundState::ShowQuestion),
-					"show-choices" => Ok(RoundState::ShowChoices),
-					"voting-time" => Ok(RoundState::VotingTime),
-					"voting-locked" => Ok(RoundState::VotingLocked),
-					"show-votes" => Ok(RoundState::ShowVotes),
-					"defense" => Ok(RoundState::Defense),
-					"show-results" => Ok(RoundState::ShowResults),
-					_ => Err(E::custom("invalid RoundState string")),
-				}
-			}
-		}
+	use crate::gamemaster::types::ChoiceOption;
 
-		deserializer.deserialize_str(RoundStateVisitor)
+	pub fn serialize<S>(votes: &[usize], serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.collect_map(votes.iter().enumerate().filter_map(|(position, count)| {
+			let option = ChoiceOption(u8::try_from(position).ok()?);
+			Some((option.letter(), count))
+		}))
 	}
-}
 
-impl<'de> serde::de::Deserialize<'de> for ChoiceOption {
-	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<usize>, D::Error>
 	where
-		D: serde::Deserializer<'de>,
+		D: Deserializer<'de>,
 	{
-		struct ChoiceOptionVisitor;
-
-		impl<'de> serde::de::Visitor<'de> for ChoiceOptionVisitor {
-			type Value = ChoiceOption;
-
-			fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-				formatter.write_str("a ChoiceOption string")
-			}
-
-			fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-			where
-				E: serde::de::Error,
-			{
-				match value {
-					"a" => Ok(ChoiceOption::ChoiceA),
-					"b" => Ok(ChoiceOption::ChoiceB),
-					_ => Err(E::custom("invalid ChoiceOption string")),
-				}
+		let by_letter: BTreeMap<String, usize> = BTreeMap::deserialize(deserializer)?;
+
+		let mut votes = Vec::new();
+		for (letter, count) in by_letter {
+			let option = ChoiceOption::from_letter(&letter)
+				.ok_or(de::Error::custom("invalid ChoiceOption letter"))?;
+			if votes.len() <= option.index() {
+				votes.resize(option.index() + 1, 0);
 			}
+			votes[option.index()] = count;
 		}
 
-		deserializer.deserialize_str(ChoiceOptionVisitor)
+		Ok(votes)
 	}
 }
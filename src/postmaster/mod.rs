@@ -1,3 +1,5 @@
 pub mod json;
+#[allow(clippy::module_inception)]
 pub mod postmaster;
+pub mod protocol;
 pub mod types;
@@ -1,38 +1,49 @@
-use std::{net::SocketAddr, thread, time::Duration};
+use std::net::SocketAddr;
 
-use crossbeam::channel::{unbounded, Sender};
-use futures_util::{future, stream::SplitSink, SinkExt, StreamExt};
-use log::{error, info};
-use tokio::net::TcpStream;
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
+use log::{debug, error, info};
+use tokio::{
+	net::TcpStream,
+	sync::mpsc::{channel, Sender},
+};
 use tokio_tungstenite::{
-	accept_async,
+	accept_async_with_config,
 	tungstenite::{
+		protocol::{frame::coding::CloseCode, CloseFrame, WebSocketConfig},
 		Error as TungsteniteError, Message as TungsteniteMessage, Result as TungsteniteResult,
 	},
 	WebSocketStream,
 };
 
-use crate::gamemaster::types::{ChoiceOption, Round};
+use crate::{
+	config::config::ConfigAccess,
+	gamemaster::{
+		error::{ErrorCode, GameError},
+		plan::PlanFormat,
+		types::{ChoiceOption, Round, RoundState},
+	},
+};
 
 use super::{
 	json::parser::parse_message,
-	json::response::{
-		make_json_active_players, make_json_game_state, make_json_not_okay_response,
-		make_json_okay_response, make_json_organizer_identity_response, make_json_player_choice,
-		make_json_player_identity_response, make_json_round, make_json_updated_choices,
-		make_json_updated_player,
+	protocol::{
+		is_supported_version, ClientHello, ServerHello, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
 	},
 	types::{
-		InternalMessage, InternalMessageAction, ResponseIdentifier, WebSocketMessage,
-		WebSocketMessageAction,
+		InternalMessage, InternalMessageAction, LateVote, LoadPlan, LoginOrganizer, MarkChoice,
+		MarkPlayer, PlayerChoice, PlayerIdentity, PlayerPoints, ResponseIdentifier, StartTimer,
+		VoteHistory, VoteHistoryQuery, WebSocketMessage, WebSocketMessageAction, WebSocketResponse,
+		WebSocketResponseAction,
 	},
 };
 
-pub fn accept_connection(peer: SocketAddr, stream: TcpStream, sender: Sender<InternalMessage>) {
-	if let Err(e) = tokio::runtime::Runtime::new()
-		.unwrap()
-		.block_on(handle_connection(peer, stream, sender))
-	{
+pub async fn accept_connection(
+	peer: SocketAddr,
+	stream: TcpStream,
+	sender: Sender<InternalMessage>,
+	config: ConfigAccess,
+) {
+	if let Err(e) = handle_connection(peer, stream, sender, config).await {
 		match e {
 			TungsteniteError::ConnectionClosed
 			| TungsteniteError::Protocol(_)
@@ -46,8 +57,14 @@ async fn handle_connection(
 	address: SocketAddr,
 	stream: TcpStream,
 	gm_channel_sender: Sender<InternalMessage>,
+	config: ConfigAccess,
 ) -> TungsteniteResult<()> {
-	let ws_stream = accept_async(stream).await;
+	let ws_config = WebSocketConfig {
+		max_message_size: Some(config.max_message_size),
+		max_frame_size: Some(config.max_message_size),
+		..Default::default()
+	};
+	let ws_stream = accept_async_with_config(stream, Some(ws_config)).await;
 	if ws_stream.is_err() {
 		return Err(TungsteniteError::ConnectionClosed);
 	}
@@ -56,22 +73,26 @@ async fn handle_connection(
 
 	let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
-	let (individual_channel_sender, individual_channel_receiver) = unbounded::<InternalMessage>();
+	// The gamemaster holds the only sender, so the channel closes once it drops this client
+	let (individual_channel_sender, mut individual_channel_receiver) =
+		channel::<InternalMessage>(config.client_outbox_size);
 
 	info!("Sending client registration: {}", address);
 	gm_channel_sender
 		.send(InternalMessage {
 			payload: InternalMessageAction::RequestRegisterClient(
 				address,
-				individual_channel_sender.clone(),
+				individual_channel_sender,
 			),
 			..Default::default()
 		})
+		.await
 		.expect("Could not send client registration message");
 
 	info!("Awaiting client registration response: {}", address);
 	let receive = individual_channel_receiver
 		.recv()
+		.await
 		.expect("Could not receive client registration response");
 
 	match receive.payload {
@@ -79,71 +100,240 @@ async fn handle_connection(
 		_ => panic!("Invalid response received from client registration process"),
 	};
 
-	loop {
+	let result = loop {
 		tokio::select! {
 			socket_message = ws_receiver.next() => {
-				match socket_message {
-					Some(message) => {
-						let message = message?;
-						if message.is_close() {
-							exit_client(&gm_channel_sender, address);
-							break;
-						}
-						if !message.is_text() && !message.is_binary() {
-							continue;
-						}
+				let message = match socket_message {
+					Some(Ok(message)) => message,
+					Some(Err(e)) => break Err(e),
+					None => break Ok(()),
+				};
+				if message.is_close() {
+					break Ok(());
+				}
+				if !message.is_text() && !message.is_binary() {
+					continue;
+				}
 
-						let message = parse_message(message.to_string());
-						if message.is_none() {
-							continue;
+				// Requests that can't be understood are answered here, the gamemaster never sees them
+				let message = match parse_message(message.to_string()) {
+					Ok(message) => message,
+					Err(invalid) => {
+						debug!("Invalid message from {}: {}", address, invalid.error);
+						let internal_message = InternalMessage {
+							payload: InternalMessageAction::ResponseNotOkay(invalid.error),
+							response_id: invalid.response_id,
+						};
+						if let Err(e) = forward_message(&mut ws_sender, internal_message).await {
+							break Err(e);
 						}
-
-						handle_message(&gm_channel_sender, address, message.unwrap());
+						continue;
+					}
+				};
+
+				// The handshake is settled between the connection and the client
+				if let WebSocketMessageAction::Hello(hello) = message.action {
+					match greet_client(&mut ws_sender, address, message.response_id, hello).await {
+						Ok(true) => continue,
+						Ok(false) => {
+							let close_frame = CloseFrame {
+								code: CloseCode::Policy,
+								reason: "Unsupported protocol version".into(),
+							};
+							break ws_sender.send(TungsteniteMessage::Close(Some(close_frame))).await;
+						}
+						Err(e) => break Err(e),
 					}
-					None => continue,
 				}
+
+				handle_message(&gm_channel_sender, address, message).await;
 			}
-			individual_channel_message = future::lazy(|_| individual_channel_receiver.try_recv()) => {
-				if individual_channel_message.is_err() {
-					thread::sleep(Duration::from_millis(50));
-					continue;
+			individual_channel_message = individual_channel_receiver.recv() => {
+				let Some(internal_message) = individual_channel_message else {
+					// The gamemaster already let go of this client (e.g. its session was resumed elsewhere)
+					let _ = ws_sender.close().await;
+					return Ok(());
+				};
+
+				if let InternalMessageAction::CloseConnection = internal_message.payload {
+					let close_frame = CloseFrame {
+						code: CloseCode::Away,
+						reason: "Server is shutting down".into(),
+					};
+					break ws_sender.send(TungsteniteMessage::Close(Some(close_frame))).await;
 				}
 
-				let internal_message: InternalMessage = individual_channel_message.expect("Could not unwrap channel message");
-
-				forward_message(&mut ws_sender, internal_message).await?;
+				if let Err(e) = forward_message(&mut ws_sender, internal_message).await {
+					break Err(e);
+				}
 			}
 		}
-	}
+	};
+
+	// Flush the reply to a close frame, or close the socket ourselves
+	let _ = ws_sender.close().await;
+
+	exit_client(&gm_channel_sender, address).await;
 
-	Ok(())
+	result
+}
+
+// Answer a hello, returns whether the client may keep talking to the server
+async fn greet_client(
+	wss: &mut SplitSink<WebSocketStream<TcpStream>, TungsteniteMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	hello: ClientHello,
+) -> TungsteniteResult<bool> {
+	let ClientHello { version, kind } = hello;
+	info!(
+		"Client {} says hello as {} with protocol version {}",
+		address,
+		kind.as_str(),
+		version
+	);
+
+	let accepted = is_supported_version(version);
+	let payload = if accepted {
+		InternalMessageAction::ResponseHello
+	} else {
+		InternalMessageAction::ResponseNotOkay(GameError::new(
+			ErrorCode::UnsupportedVersion,
+			format!(
+				"This client speaks protocol version {}, but the server needs {} to {}. Please reload the page.",
+				version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+			),
+		))
+	};
+	forward_message(
+		wss,
+		InternalMessage {
+			payload,
+			response_id,
+		},
+	)
+	.await?;
+
+	Ok(accepted)
 }
 
-fn handle_message(gmcs: &Sender<InternalMessage>, address: SocketAddr, message: WebSocketMessage) {
+async fn handle_message(
+	gmcs: &Sender<InternalMessage>,
+	address: SocketAddr,
+	message: WebSocketMessage,
+) {
 	match message.action {
+		// Answered by handle_connection, never reaches the gamemaster
+		WebSocketMessageAction::Hello(..) => (),
 		WebSocketMessageAction::LoginPlayer(name) => {
-			log_in_player(gmcs, address, message.response_id, name)
+			log_in_player(gmcs, address, message.response_id, name).await
+		}
+		WebSocketMessageAction::ResumeSession(session_token) => {
+			resume_session(gmcs, address, message.response_id, session_token).await
+		}
+		WebSocketMessageAction::JoinRoom(code) => {
+			join_room(gmcs, address, message.response_id, code).await
+		}
+		WebSocketMessageAction::LoginOrganizer(LoginOrganizer { username, password }) => {
+			log_in_organizer(gmcs, address, message.response_id, username, password).await
+		}
+		WebSocketMessageAction::LoginDisplay => {
+			log_in_display(gmcs, address, message.response_id).await
+		}
+		WebSocketMessageAction::CreateRoom(name) => {
+			create_room(gmcs, address, message.response_id, name).await
+		}
+		WebSocketMessageAction::RetrieveRooms => {
+			retrieve_rooms(gmcs, address, message.response_id).await
 		}
-		WebSocketMessageAction::LoginOrganizer(password) => {
-			log_in_organizer(gmcs, address, message.response_id, password)
+		WebSocketMessageAction::StartGame(name) => {
+			start_game(gmcs, address, message.response_id, name).await
+		}
+		WebSocketMessageAction::RetrieveGames => {
+			retrieve_games(gmcs, address, message.response_id).await
 		}
 
-		WebSocketMessageAction::RetrieveGameState() => {
-			retrieve_game_state(gmcs, address, message.response_id)
+		WebSocketMessageAction::RetrieveGameState => {
+			retrieve_game_state(gmcs, address, message.response_id).await
 		}
 
-		WebSocketMessageAction::SetRound(round) => {
-			set_round(gmcs, address, message.response_id, round);
+		WebSocketMessageAction::SetRound(set_round_payload) => {
+			let override_transition = set_round_payload.override_transition;
+			let round = set_round_payload.into_round();
+			set_round(
+				gmcs,
+				address,
+				message.response_id,
+				round,
+				override_transition,
+			)
+			.await
+		}
+		WebSocketMessageAction::AdvanceRoundState => {
+			advance_round_state(gmcs, address, message.response_id).await
+		}
+		WebSocketMessageAction::LoadPlan(LoadPlan { format, content }) => {
+			load_plan(gmcs, address, message.response_id, format, content).await
+		}
+		WebSocketMessageAction::RetrieveGamePlan => {
+			retrieve_game_plan(gmcs, address, message.response_id).await
+		}
+		WebSocketMessageAction::NextRound => next_round(gmcs, address, message.response_id).await,
+		WebSocketMessageAction::StartTimer(StartTimer {
+			seconds,
+			advance_to,
+		}) => start_timer(gmcs, address, message.response_id, seconds, advance_to).await,
+		WebSocketMessageAction::PauseTimer => {
+			control_timer(
+				gmcs,
+				InternalMessageAction::RequestPauseTimer(address),
+				message.response_id,
+			)
+			.await
+		}
+		WebSocketMessageAction::ResumeTimer => {
+			control_timer(
+				gmcs,
+				InternalMessageAction::RequestResumeTimer(address),
+				message.response_id,
+			)
+			.await
 		}
-		WebSocketMessageAction::MarkChoice(id, lie) => {
-			mark_choice(gmcs, address, message.response_id, id, lie);
+		WebSocketMessageAction::ExtendTimer(seconds) => {
+			control_timer(
+				gmcs,
+				InternalMessageAction::RequestExtendTimer(address, seconds),
+				message.response_id,
+			)
+			.await
+		}
+		WebSocketMessageAction::CancelTimer => {
+			control_timer(
+				gmcs,
+				InternalMessageAction::RequestCancelTimer(address),
+				message.response_id,
+			)
+			.await
+		}
+		WebSocketMessageAction::SetVoteIsLie(MarkChoice { id, lie }) => {
+			mark_choice(gmcs, address, message.response_id, id, lie).await
 		}
 		WebSocketMessageAction::SetChoiceOption(option) => {
-			set_choice_option(gmcs, address, message.response_id, option);
+			set_choice_option(gmcs, address, message.response_id, option).await
+		}
+		WebSocketMessageAction::SetLateVote(LateVote { player_id, option }) => {
+			set_late_vote(gmcs, address, message.response_id, player_id, option).await
+		}
+		WebSocketMessageAction::SetPlayerCanVote(MarkPlayer { id, can_vote }) => {
+			mark_player(gmcs, address, message.response_id, id, None, can_vote).await
 		}
-		WebSocketMessageAction::MarkPlayer(id, points, can_vote) => {
-			mark_player(gmcs, address, message.response_id, id, points, can_vote)
+		WebSocketMessageAction::SetPlayerPoints(PlayerPoints { id, points }) => {
+			mark_player(gmcs, address, message.response_id, id, points, None).await
 		}
+		WebSocketMessageAction::RetrieveVoteHistory(VoteHistoryQuery {
+			round_id,
+			player_id,
+		}) => retrieve_vote_history(gmcs, address, message.response_id, round_id, player_id).await,
 	};
 }
 
@@ -151,44 +341,77 @@ async fn forward_message(
 	wss: &mut SplitSink<WebSocketStream<TcpStream>, TungsteniteMessage>,
 	internal_message: InternalMessage,
 ) -> TungsteniteResult<()> {
-	let json: serde_json::Value = match internal_message.payload {
-		InternalMessageAction::ResponseOkay => {
-			make_json_okay_response(internal_message.response_id)
+	let mut code = None;
+	let action = match internal_message.payload {
+		InternalMessageAction::ResponseOkay => WebSocketResponseAction::Ok,
+		InternalMessageAction::ResponseHello => {
+			WebSocketResponseAction::Hello(ServerHello::current())
 		}
-		InternalMessageAction::ResponseNotOkay(message) => {
-			make_json_not_okay_response(internal_message.response_id, message)
+		InternalMessageAction::ResponseNotOkay(error) => {
+			code = Some(error.code);
+			WebSocketResponseAction::Ng(error.message)
 		}
-		InternalMessageAction::ResponsePlayerIdentity(player) => {
-			make_json_player_identity_response(internal_message.response_id, player)
+		InternalMessageAction::ResponsePlayerIdentity(player, session_token) => {
+			WebSocketResponseAction::SetPlayer(PlayerIdentity {
+				player,
+				session_token,
+			})
 		}
 		InternalMessageAction::ResponseOrganizerIdentity(organizer) => {
-			make_json_organizer_identity_response(internal_message.response_id, organizer)
+			WebSocketResponseAction::SetOrganizer(organizer)
+		}
+		InternalMessageAction::ResponseDisplayIdentity(room) => {
+			WebSocketResponseAction::SetDisplay(room)
 		}
 		InternalMessageAction::ResponseActivePlayers(active_players) => {
-			make_json_active_players(internal_message.response_id, active_players)
+			WebSocketResponseAction::RefreshActivePlayersList(active_players)
 		}
 		InternalMessageAction::ResponseUpdatedPlayer(player) => {
-			make_json_updated_player(internal_message.response_id, player)
+			WebSocketResponseAction::UpdatePlayer(player)
 		}
 		InternalMessageAction::ResponseGameState(game_state) => {
-			make_json_game_state(internal_message.response_id, game_state)
+			WebSocketResponseAction::SetGameState(game_state)
 		}
+		InternalMessageAction::ResponseRoom(room) => WebSocketResponseAction::SetRoom(room),
+		InternalMessageAction::ResponseRooms(rooms) => WebSocketResponseAction::SetRooms(rooms),
+		InternalMessageAction::ResponseGames(games) => WebSocketResponseAction::SetGames(games),
 		InternalMessageAction::ResponsePlayerChoice(player, choice) => {
-			make_json_player_choice(internal_message.response_id, player, choice)
+			WebSocketResponseAction::SetPlayerChoice(PlayerChoice { player, choice })
+		}
+		InternalMessageAction::ResponseRound(round) => WebSocketResponseAction::SetRound(round),
+		InternalMessageAction::ResponseRoundResult(result) => {
+			WebSocketResponseAction::SetRoundResult(result)
 		}
-		InternalMessageAction::ResponseRound(round) => {
-			make_json_round(internal_message.response_id, round)
+		InternalMessageAction::ResponseGamePlan(plan) => WebSocketResponseAction::SetGamePlan(plan),
+		InternalMessageAction::ResponseVoteHistory(round_id, player_id, events) => {
+			WebSocketResponseAction::SetVoteHistory(VoteHistory {
+				round_id,
+				player_id,
+				events,
+			})
 		}
+		InternalMessageAction::ResponseTimer(timer) => WebSocketResponseAction::SetTimer(timer),
 		InternalMessageAction::ResponseUpdatedChoices(choices_map) => {
-			make_json_updated_choices(internal_message.response_id, choices_map)
+			WebSocketResponseAction::SetChoices(choices_map)
+		}
+		InternalMessageAction::ResponseServerShuttingDown => {
+			WebSocketResponseAction::ServerShuttingDown(
+				"The game server is shutting down.".to_owned(),
+			)
 		}
 		_ => return Ok(()),
 	};
 
-	wss.send(TungsteniteMessage::Text(json.to_string())).await
+	let response = WebSocketResponse {
+		response_id: internal_message.response_id,
+		code,
+		action,
+	};
+	let json = serde_json::to_string(&response).expect("Could not serialize a response");
+	wss.send(TungsteniteMessage::Text(json)).await
 }
 
-fn log_in_player(
+async fn log_in_player(
 	sender: &Sender<InternalMessage>,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
@@ -197,40 +420,154 @@ fn log_in_player(
 	let internal_message = InternalMessage {
 		payload: InternalMessageAction::RequestRegisterActivePlayer(address, name),
 		response_id,
-		..Default::default()
 	};
 	sender
 		.send(internal_message)
+		.await
 		.expect("Could not send request to GM for logging in player");
 }
 
-fn log_in_organizer(
+async fn resume_session(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	session_token: String,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestResumeSession(address, session_token),
+		response_id,
+	};
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for resuming session");
+}
+
+async fn join_room(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	code: String,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestJoinRoom(address, code),
+		response_id,
+	};
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for joining a room");
+}
+
+async fn log_in_display(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestRegisterDisplay(address),
+		response_id,
+	};
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for logging in display");
+}
+
+async fn create_room(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	name: String,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestCreateRoom(address, name),
+		response_id,
+	};
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for creating a room");
+}
+
+async fn retrieve_rooms(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestRooms(address),
+		response_id,
+	};
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for rooms");
+}
+
+async fn start_game(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	name: String,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestStartGame(address, name),
+		response_id,
+	};
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for starting a game");
+}
+
+async fn retrieve_games(
 	sender: &Sender<InternalMessage>,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestGames(address),
+		response_id,
+	};
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for games");
+}
+
+async fn log_in_organizer(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	username: String,
 	password: String,
 ) {
 	let internal_message = InternalMessage {
-		payload: InternalMessageAction::RequestRegisterOrganizer(address, password),
+		payload: InternalMessageAction::RequestRegisterOrganizer(address, username, password),
 		response_id,
-		..Default::default()
 	};
 	sender
 		.send(internal_message)
+		.await
 		.expect("Could not send request to GM for logging in organizer");
 }
 
-fn exit_client(sender: &Sender<InternalMessage>, address: SocketAddr) {
+async fn exit_client(sender: &Sender<InternalMessage>, address: SocketAddr) {
 	let internal_message = InternalMessage {
 		payload: InternalMessageAction::ExitClient(address),
 		..Default::default()
 	};
-	sender
-		.send(internal_message)
-		.expect("Could not send request to GM for goodbye sengen");
+	// The gamemaster may already be gone when the server is shutting down
+	if sender.send(internal_message).await.is_err() {
+		debug!(
+			"Could not send goodbye message for {}, gamemaster is gone",
+			address
+		);
+	}
 }
 
-fn retrieve_game_state(
+async fn retrieve_game_state(
 	sender: &Sender<InternalMessage>,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
@@ -238,32 +575,133 @@ fn retrieve_game_state(
 	let internal_message = InternalMessage {
 		payload: InternalMessageAction::RequestGameState(address),
 		response_id,
-		..Default::default()
 	};
 
 	sender
 		.send(internal_message)
+		.await
 		.expect("Could not send request to GM for game state");
 }
 
-fn set_round(
+async fn set_round(
 	sender: &Sender<InternalMessage>,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
 	round: Round,
+	override_transition: bool,
 ) {
 	let internal_message = InternalMessage {
-		payload: InternalMessageAction::RequestSetRound(address, round),
+		payload: InternalMessageAction::RequestSetRound(address, round, override_transition),
 		response_id,
-		..Default::default()
 	};
 
 	sender
 		.send(internal_message)
+		.await
 		.expect("Could not send request to GM for setting round");
 }
 
-fn set_choice_option(
+async fn advance_round_state(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestAdvanceRoundState(address),
+		response_id,
+	};
+
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for advancing round state");
+}
+
+async fn load_plan(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	format: PlanFormat,
+	content: String,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestLoadPlan(address, format, content),
+		response_id,
+	};
+
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for loading a game plan");
+}
+
+async fn retrieve_game_plan(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestGamePlan(address),
+		response_id,
+	};
+
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for game plan");
+}
+
+async fn next_round(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestNextRound(address),
+		response_id,
+	};
+
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for next round");
+}
+
+async fn start_timer(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	seconds: u64,
+	advance_to: Option<RoundState>,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestStartTimer(address, seconds, advance_to),
+		response_id,
+	};
+
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for starting timer");
+}
+
+async fn control_timer(
+	sender: &Sender<InternalMessage>,
+	request: InternalMessageAction,
+	response_id: ResponseIdentifier,
+) {
+	let internal_message = InternalMessage {
+		payload: request,
+		response_id,
+	};
+
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for controlling timer");
+}
+
+async fn set_choice_option(
 	sender: &Sender<InternalMessage>,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
@@ -272,47 +710,83 @@ fn set_choice_option(
 	let internal_message = InternalMessage {
 		payload: InternalMessageAction::RequestSetChoiceOption(address, option),
 		response_id,
-		..Default::default()
 	};
 
 	sender
 		.send(internal_message)
+		.await
 		.expect("Could not send request to GM for setting choice");
 }
 
-fn mark_choice(
+async fn mark_choice(
 	sender: &Sender<InternalMessage>,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
-	id: u8,
+	id: i64,
 	lie: Option<bool>,
 ) {
 	let internal_message = InternalMessage {
 		payload: InternalMessageAction::RequestMarkChoice(address, id, lie),
 		response_id,
-		..Default::default()
 	};
 
 	sender
 		.send(internal_message)
+		.await
 		.expect("Could not send request to GM for setting choice");
 }
 
-fn mark_player(
+async fn mark_player(
 	sender: &Sender<InternalMessage>,
 	address: SocketAddr,
 	response_id: ResponseIdentifier,
-	id: u8,
+	id: i64,
 	points: Option<usize>,
 	can_vote: Option<bool>,
 ) {
 	let internal_message = InternalMessage {
 		payload: InternalMessageAction::RequestMarkPlayer(address, id, points, can_vote),
 		response_id,
-		..Default::default()
 	};
 
 	sender
 		.send(internal_message)
+		.await
 		.expect("Could not send request to GM for setting choice");
 }
+
+async fn set_late_vote(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	player_id: i64,
+	option: ChoiceOption,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestSetLateVote(address, player_id, option),
+		response_id,
+	};
+
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for setting late vote");
+}
+
+async fn retrieve_vote_history(
+	sender: &Sender<InternalMessage>,
+	address: SocketAddr,
+	response_id: ResponseIdentifier,
+	round_id: i64,
+	player_id: i64,
+) {
+	let internal_message = InternalMessage {
+		payload: InternalMessageAction::RequestVoteHistory(address, round_id, player_id),
+		response_id,
+	};
+
+	sender
+		.send(internal_message)
+		.await
+		.expect("Could not send request to GM for vote history");
+}
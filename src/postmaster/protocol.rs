@@ -0,0 +1,105 @@
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use ts_rs::TS;
+
+// Bumped whenever an action or payload changes in a way older clients can't follow
+pub const PROTOCOL_VERSION: u32 = 1;
+// The oldest client version the server still understands
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+// Actions clients can send, announced in the hello response
+pub const ACTIONS: &[&str] = &[
+	"hello",
+	"login-player",
+	"resume-session",
+	"join-room",
+	"create-room",
+	"get-rooms",
+	"start-game",
+	"get-games",
+	"login-organizer",
+	"login-display",
+	"get-game-state",
+	"set-round",
+	"advance-round-state",
+	"load-plan",
+	"get-game-plan",
+	"next-round",
+	"start-timer",
+	"pause-timer",
+	"resume-timer",
+	"extend-timer",
+	"cancel-timer",
+	"set-vote-is-lie",
+	"set-choice",
+	"set-late-vote",
+	"set-player-can-vote",
+	"set-player-points",
+	"get-vote-history",
+];
+
+// Optional behaviour clients may rely on when the server lists it
+pub const FEATURES: &[&str] = &[
+	"rooms",
+	"games",
+	"display",
+	"session-resume",
+	"game-plans",
+	"timers",
+	"late-votes",
+	"vote-history",
+	"hidden-votes",
+	"error-codes",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientKind {
+	Player,
+	Organizer,
+	Display,
+	Bot,
+}
+
+impl ClientKind {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			ClientKind::Player => "player",
+			ClientKind::Organizer => "organizer",
+			ClientKind::Display => "display",
+			ClientKind::Bot => "bot",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+pub struct ClientHello {
+	pub version: u32,
+	pub kind: ClientKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerHello {
+	pub version: u32,
+	pub min_version: u32,
+	pub server: String,
+	pub actions: Vec<String>,
+	pub features: Vec<String>,
+}
+
+impl ServerHello {
+	pub fn current() -> ServerHello {
+		ServerHello {
+			version: PROTOCOL_VERSION,
+			min_version: MIN_PROTOCOL_VERSION,
+			server: env!("CARGO_PKG_VERSION").to_owned(),
+			actions: ACTIONS.iter().map(|action| action.to_string()).collect(),
+			features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+		}
+	}
+}
+
+pub fn is_supported_version(version: u32) -> bool {
+	(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
@@ -1,9 +1,21 @@
 use std::net::SocketAddr;
 
-use crossbeam::channel::Sender;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use ts_rs::TS;
 
-use crate::gamemaster::types::{
-	Choice, ChoiceOption, ChoicesMap, GameState, Organizer, Player, PlayerMap, Round,
+use crate::{
+	gamemaster::{
+		error::{ErrorCode, GameError},
+		plan::PlanFormat,
+		timer::TimerStatus,
+		types::{
+			Choice, ChoiceEvent, ChoiceOption, ChoicesMap, Game, GameState, Organizer,
+			PlannedRound, Player, PlayerMap, Room, Round, RoundResult, RoundState,
+		},
+	},
+	postmaster::protocol::{ClientHello, ServerHello},
 };
 
 pub type ResponseIdentifier = Option<String>;
@@ -12,32 +24,102 @@ pub type ResponseIdentifier = Option<String>;
 pub enum InternalMessageAction {
 	// General responses
 	ResponseOkay,
-	ResponseNotOkay(String),
+	ResponseNotOkay(GameError),
 
-	ResponsePlayerIdentity(Player),
+	ResponsePlayerIdentity(Player, String),
 	ResponseActivePlayers(PlayerMap),
 	ResponseUpdatedPlayer(Player),
-	ResponseGameState(GameState),
+	ResponseGameState(Box<GameState>),
+	ResponseRoom(Room),
 	ResponseRound(Round),
+	ResponseRoundResult(RoundResult),
+	ResponseTimer(Option<TimerStatus>),
 	ResponseUpdatedChoices(ChoicesMap),
 
 	ResponseOrganizerIdentity(Organizer),
+	ResponseDisplayIdentity(Room),
 	ResponsePlayerChoice(Player, Choice),
+	ResponseGamePlan(Vec<PlannedRound>),
+	ResponseRooms(Vec<Room>),
+	ResponseGames(Vec<Game>),
+	ResponseVoteHistory(i64, i64, Vec<ChoiceEvent>),
+
+	// From Postmaster to Client, answering a hello
+	ResponseHello,
+
+	// From GM to Client when the server stops
+	ResponseServerShuttingDown,
+	CloseConnection,
 
 	// From Client to GM
 	ExitClient(SocketAddr),
 	RequestRegisterClient(SocketAddr, Sender<InternalMessage>),
 
+	// From Server to GM
+	RequestShutdown,
+
 	// From Player client to GM
 	RequestRegisterActivePlayer(SocketAddr, String),
+	RequestResumeSession(SocketAddr, String),
+	RequestJoinRoom(SocketAddr, String),
 	RequestGameState(SocketAddr),
 	RequestSetChoiceOption(SocketAddr, ChoiceOption),
 
 	// From Organizer client to GM
-	RequestRegisterOrganizer(SocketAddr, String),
-	RequestSetRound(SocketAddr, Round),
-	RequestMarkPlayer(SocketAddr, u8, Option<usize>, Option<bool>),
-	RequestMarkChoice(SocketAddr, u8, Option<bool>),
+	RequestRegisterOrganizer(SocketAddr, String, String),
+	RequestRegisterDisplay(SocketAddr),
+	RequestCreateRoom(SocketAddr, String),
+	RequestRooms(SocketAddr),
+	RequestStartGame(SocketAddr, String),
+	RequestGames(SocketAddr),
+	RequestSetRound(SocketAddr, Round, bool),
+	RequestAdvanceRoundState(SocketAddr),
+	RequestLoadPlan(SocketAddr, PlanFormat, String),
+	RequestGamePlan(SocketAddr),
+	RequestNextRound(SocketAddr),
+	RequestStartTimer(SocketAddr, u64, Option<RoundState>),
+	RequestPauseTimer(SocketAddr),
+	RequestResumeTimer(SocketAddr),
+	RequestExtendTimer(SocketAddr, u64),
+	RequestCancelTimer(SocketAddr),
+	RequestMarkPlayer(SocketAddr, i64, Option<usize>, Option<bool>),
+	RequestMarkChoice(SocketAddr, i64, Option<bool>),
+	RequestSetLateVote(SocketAddr, i64, ChoiceOption),
+	RequestVoteHistory(SocketAddr, i64, i64),
+}
+
+impl InternalMessageAction {
+	// The client that is waiting for a response to this request, if any
+	pub fn requester(&self) -> Option<SocketAddr> {
+		match self {
+			InternalMessageAction::RequestRegisterActivePlayer(address, _)
+			| InternalMessageAction::RequestResumeSession(address, _)
+			| InternalMessageAction::RequestJoinRoom(address, _)
+			| InternalMessageAction::RequestGameState(address)
+			| InternalMessageAction::RequestSetChoiceOption(address, _)
+			| InternalMessageAction::RequestRegisterOrganizer(address, _, _)
+			| InternalMessageAction::RequestRegisterDisplay(address)
+			| InternalMessageAction::RequestCreateRoom(address, _)
+			| InternalMessageAction::RequestRooms(address)
+			| InternalMessageAction::RequestStartGame(address, _)
+			| InternalMessageAction::RequestGames(address)
+			| InternalMessageAction::RequestSetRound(address, _, _)
+			| InternalMessageAction::RequestAdvanceRoundState(address)
+			| InternalMessageAction::RequestLoadPlan(address, _, _)
+			| InternalMessageAction::RequestGamePlan(address)
+			| InternalMessageAction::RequestNextRound(address)
+			| InternalMessageAction::RequestStartTimer(address, _, _)
+			| InternalMessageAction::RequestPauseTimer(address)
+			| InternalMessageAction::RequestResumeTimer(address)
+			| InternalMessageAction::RequestExtendTimer(address, _)
+			| InternalMessageAction::RequestCancelTimer(address)
+			| InternalMessageAction::RequestMarkPlayer(address, _, _, _)
+			| InternalMessageAction::RequestMarkChoice(address, _, _)
+			| InternalMessageAction::RequestSetLateVote(address, _, _)
+			| InternalMessageAction::RequestVoteHistory(address, _, _) => Some(*address),
+			_ => None,
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -55,19 +137,222 @@ impl Default for InternalMessage {
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+pub struct LoginOrganizer {
+	pub username: String,
+	pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRound {
+	#[ts(type = "number")]
+	pub id: i64,
+	pub number: u8,
+	pub phase: u8,
+	pub state: RoundState,
+	pub question: String,
+	#[serde(default)]
+	#[ts(as = "Option<Vec<String>>", optional)]
+	pub options: Vec<String>,
+	// Clients that only know about two choices send these instead of options
+	#[serde(default)]
+	#[ts(optional)]
+	pub choice_a: Option<String>,
+	#[serde(default)]
+	#[ts(optional)]
+	pub choice_b: Option<String>,
+	// Skip the state transition checks, for corrections
+	#[serde(default, rename = "override")]
+	#[ts(as = "Option<bool>", optional)]
+	pub override_transition: bool,
+}
+
+impl SetRound {
+	pub fn into_round(self) -> Round {
+		let options = if self.options.is_empty() {
+			self.choice_a.into_iter().chain(self.choice_b).collect()
+		} else {
+			self.options
+		};
+
+		Round {
+			id: self.id,
+			number: self.number,
+			phase: self.phase,
+			state: self.state,
+			question: self.question,
+			options,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+pub struct LoadPlan {
+	pub format: PlanFormat,
+	pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct StartTimer {
+	#[ts(type = "number")]
+	pub seconds: u64,
+	#[serde(default)]
+	#[ts(optional)]
+	pub advance_to: Option<RoundState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+pub struct MarkChoice {
+	#[ts(type = "number")]
+	pub id: i64,
+	#[serde(default)]
+	#[ts(optional)]
+	pub lie: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkPlayer {
+	#[ts(type = "number")]
+	pub id: i64,
+	#[serde(default)]
+	#[ts(optional)]
+	pub can_vote: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+pub struct PlayerPoints {
+	#[ts(type = "number")]
+	pub id: i64,
+	#[serde(default)]
+	#[ts(optional)]
+	pub points: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct LateVote {
+	#[ts(type = "number")]
+	pub player_id: i64,
+	pub option: ChoiceOption,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct VoteHistoryQuery {
+	#[ts(type = "number")]
+	pub round_id: i64,
+	#[ts(type = "number")]
+	pub player_id: i64,
+}
+
+// Requests from clients, named by their action and carrying their payload
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(tag = "action", content = "payload", rename_all = "kebab-case")]
 pub enum WebSocketMessageAction {
+	Hello(ClientHello),
 	LoginPlayer(String),
-	LoginOrganizer(String),
-	RetrieveGameState(),
-	SetRound(Round),
+	ResumeSession(String),
+	JoinRoom(String),
+	LoginOrganizer(LoginOrganizer),
+	LoginDisplay,
+	CreateRoom(String),
+	#[serde(rename = "get-rooms")]
+	RetrieveRooms,
+	StartGame(String),
+	#[serde(rename = "get-games")]
+	RetrieveGames,
+	#[serde(rename = "get-game-state")]
+	RetrieveGameState,
+	SetRound(SetRound),
+	AdvanceRoundState,
+	LoadPlan(LoadPlan),
+	#[serde(rename = "get-game-plan")]
+	RetrieveGamePlan,
+	NextRound,
+	StartTimer(StartTimer),
+	PauseTimer,
+	ResumeTimer,
+	ExtendTimer(#[ts(type = "number")] u64),
+	CancelTimer,
+	#[serde(rename = "set-choice")]
 	SetChoiceOption(ChoiceOption),
-	MarkPlayer(u8, Option<usize>, Option<bool>),
-	MarkChoice(u8, Option<bool>),
+	SetVoteIsLie(MarkChoice),
+	SetLateVote(LateVote),
+	SetPlayerCanVote(MarkPlayer),
+	SetPlayerPoints(PlayerPoints),
+	#[serde(rename = "get-vote-history")]
+	RetrieveVoteHistory(VoteHistoryQuery),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
 pub struct WebSocketMessage {
+	#[serde(default)]
 	pub response_id: ResponseIdentifier,
+	#[serde(flatten)]
 	pub action: WebSocketMessageAction,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerIdentity {
+	pub player: Player,
+	pub session_token: String,
+}
+
+// Every vote a player submitted in a round, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct VoteHistory {
+	#[ts(type = "number")]
+	pub round_id: i64,
+	#[ts(type = "number")]
+	pub player_id: i64,
+	pub events: Vec<ChoiceEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+pub struct PlayerChoice {
+	pub player: Player,
+	pub choice: Choice,
+}
+
+// Everything the server sends to clients, named by their action
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(tag = "action", content = "payload", rename_all = "kebab-case")]
+pub enum WebSocketResponseAction {
+	Ok,
+	Ng(String),
+	Hello(ServerHello),
+	SetPlayer(PlayerIdentity),
+	SetOrganizer(Organizer),
+	SetDisplay(Room),
+	RefreshActivePlayersList(#[ts(type = "{ [key in number]?: Player }")] PlayerMap),
+	UpdatePlayer(Player),
+	SetGameState(Box<GameState>),
+	SetRoom(Room),
+	SetRooms(Vec<Room>),
+	SetGames(Vec<Game>),
+	SetRound(Round),
+	SetRoundResult(RoundResult),
+	SetTimer(Option<TimerStatus>),
+	SetChoices(#[ts(type = "{ [key in number]?: Choice }")] ChoicesMap),
+	SetPlayerChoice(PlayerChoice),
+	SetGamePlan(Vec<PlannedRound>),
+	SetVoteHistory(VoteHistory),
+	ServerShuttingDown(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketResponse {
+	pub response_id: ResponseIdentifier,
+	// Null except on ng
+	#[serde(default)]
+	pub code: Option<ErrorCode>,
+	#[serde(flatten)]
+	pub action: WebSocketResponseAction,
+}